@@ -0,0 +1,67 @@
+/// A puzzle answer, in the form the puzzle asks for.
+pub trait Answer {
+    /// The answer as it would be submitted: a single line with no decoration.
+    fn canonical(&self) -> String;
+
+    /// A fuller, possibly multi-line, rendering for answers that have one.
+    fn render(&self) -> Option<String> {
+        None
+    }
+
+    /// Compares against a stored answer, ignoring surrounding whitespace.
+    fn check(&self, expected: &str) -> bool {
+        self.canonical() == expected.trim()
+    }
+}
+
+macro_rules! display_answer {
+    ($($t:ty),*) => {
+        $(
+            impl Answer for $t {
+                fn canonical(&self) -> String {
+                    self.to_string()
+                }
+            }
+        )*
+    };
+}
+
+display_answer!(u16, u32, u64, u128, usize, i32, i64, String, &str);
+
+/// For puzzles with nothing to answer, like the last day's second part.
+impl Answer for () {
+    fn canonical(&self) -> String {
+        String::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    struct Picture;
+
+    impl Answer for Picture {
+        fn canonical(&self) -> String {
+            "2".into()
+        }
+
+        fn render(&self) -> Option<String> {
+            Some("#.\n.#".into())
+        }
+    }
+
+    #[test]
+    fn test_answers() {
+        assert_eq!(42_u64.canonical(), "42");
+        assert_eq!((-7_i64).canonical(), "-7");
+        assert_eq!(String::from("a,b").canonical(), "a,b");
+        assert!(().check(""));
+        assert!(42_usize.check(" 42\n"));
+        assert!(!42_usize.check("042"));
+        assert_eq!(42_u32.render(), None);
+
+        assert!(Picture.check("2"));
+        assert_eq!(Picture.render().unwrap().lines().count(), 2);
+    }
+}
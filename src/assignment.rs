@@ -0,0 +1,265 @@
+use crate::Error;
+
+use itertools::Itertools;
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    hash::Hash,
+};
+
+/// Candidate values for each key; an assignment gives every key a distinct value.
+pub type Candidates<K, V> = HashMap<K, HashSet<V>>;
+
+/// Repeatedly removes values that some key is already pinned to from every other key, until
+/// nothing changes. Returns whether any key was left without candidates.
+pub fn eliminate<K, V>(candidates: &mut Candidates<K, V>) -> bool
+where
+    K: Clone + Eq + Hash,
+    V: Clone + Eq + Hash,
+{
+    loop {
+        let pinned = candidates
+            .iter()
+            .filter_map(|(k, vs)| Some((k.clone(), vs.iter().exactly_one().ok()?.clone())))
+            .collect_vec();
+
+        let mut changed = false;
+        for (key, value) in &pinned {
+            for (k, vs) in candidates.iter_mut() {
+                if k != key {
+                    changed |= vs.remove(value);
+                }
+            }
+        }
+
+        if candidates.values().any(HashSet::is_empty) {
+            return true;
+        }
+        if !changed {
+            return false;
+        }
+    }
+}
+
+/// Maximum bipartite matching; returns the right-hand partner of each left-hand vertex.
+fn hopcroft_karp(adj: &[Vec<usize>], n_right: usize) -> Vec<Option<usize>> {
+    const INF: usize = usize::MAX;
+    let n_left = adj.len();
+    let mut match_left = vec![None; n_left];
+    let mut match_right = vec![None; n_right];
+    let mut dist = vec![INF; n_left];
+
+    fn augment(
+        u: usize,
+        adj: &[Vec<usize>],
+        match_left: &mut [Option<usize>],
+        match_right: &mut [Option<usize>],
+        dist: &mut [usize],
+    ) -> bool {
+        for &v in &adj[u] {
+            let next = match match_right[v] {
+                None => true,
+                Some(w) => dist[w] == dist[u] + 1 && augment(w, adj, match_left, match_right, dist),
+            };
+            if next {
+                match_left[u] = Some(v);
+                match_right[v] = Some(u);
+                return true;
+            }
+        }
+        dist[u] = INF;
+        false
+    }
+
+    loop {
+        let mut queue = VecDeque::new();
+        for u in 0..n_left {
+            if match_left[u].is_none() {
+                dist[u] = 0;
+                queue.push_back(u);
+            } else {
+                dist[u] = INF;
+            }
+        }
+
+        let mut found = false;
+        while let Some(u) = queue.pop_front() {
+            for &v in &adj[u] {
+                match match_right[v] {
+                    None => found = true,
+                    Some(w) if dist[w] == INF => {
+                        dist[w] = dist[u] + 1;
+                        queue.push_back(w);
+                    }
+                    Some(_) => (),
+                }
+            }
+        }
+        if !found {
+            break;
+        }
+
+        for u in 0..n_left {
+            if match_left[u].is_none() {
+                augment(u, adj, &mut match_left, &mut match_right, &mut dist);
+            }
+        }
+    }
+    match_left
+}
+
+/// Collects perfect matchings into `out` until it holds `limit` of them.
+fn all_matchings(
+    adj: &[Vec<usize>],
+    order: &[usize],
+    used: &mut HashSet<usize>,
+    current: &mut Vec<Option<usize>>,
+    out: &mut Vec<Vec<usize>>,
+    limit: usize,
+) {
+    if out.len() >= limit {
+        return;
+    }
+    let (&u, rest) = match order.split_first() {
+        Some(split) => split,
+        None => {
+            out.push(current.iter().map(|v| v.unwrap()).collect());
+            return;
+        }
+    };
+    for &v in &adj[u] {
+        if used.insert(v) {
+            current[u] = Some(v);
+            all_matchings(adj, rest, used, current, out, limit);
+            used.remove(&v);
+        }
+    }
+    current[u] = None;
+}
+
+/// Every assignment consistent with `candidates`. Elimination settles what it can, and whatever
+/// is left is checked with a maximum matching before enumerating the alternatives.
+pub fn all<K, V>(candidates: &Candidates<K, V>) -> Vec<HashMap<K, V>>
+where
+    K: Clone + Eq + Hash + Ord,
+    V: Clone + Eq + Hash + Ord,
+{
+    up_to(candidates, usize::MAX)
+}
+
+/// Like [`all`], but stops enumerating once `limit` assignments have been found.
+pub fn up_to<K, V>(candidates: &Candidates<K, V>, limit: usize) -> Vec<HashMap<K, V>>
+where
+    K: Clone + Eq + Hash + Ord,
+    V: Clone + Eq + Hash + Ord,
+{
+    let mut candidates = candidates.clone();
+    if eliminate(&mut candidates) {
+        return Vec::new();
+    }
+
+    let keys = candidates.keys().sorted().collect_vec();
+    let values = candidates.values().flatten().sorted().dedup().collect_vec();
+    let index: HashMap<_, _> = values.iter().enumerate().map(|(i, &v)| (v, i)).collect();
+    let adj = keys
+        .iter()
+        .map(|k| {
+            candidates[k]
+                .iter()
+                .map(|v| index[v])
+                .sorted()
+                .collect_vec()
+        })
+        .collect_vec();
+
+    if hopcroft_karp(&adj, values.len())
+        .iter()
+        .any(Option::is_none)
+    {
+        return Vec::new();
+    }
+
+    let order = (0..keys.len())
+        .sorted_by_key(|&u| adj[u].len())
+        .collect_vec();
+    let mut matchings = Vec::new();
+    all_matchings(
+        &adj,
+        &order,
+        &mut HashSet::new(),
+        &mut vec![None; keys.len()],
+        &mut matchings,
+        limit,
+    );
+
+    matchings
+        .into_iter()
+        .map(|matching| {
+            keys.iter()
+                .zip(matching)
+                .map(|(&k, v)| (k.clone(), values[v].clone()))
+                .collect()
+        })
+        .collect()
+}
+
+/// The only assignment consistent with `candidates`.
+pub fn unique<K, V>(candidates: &Candidates<K, V>) -> Result<HashMap<K, V>, Error>
+where
+    K: Clone + Eq + Hash + Ord,
+    V: Clone + Eq + Hash + Ord,
+{
+    // a second assignment is enough to know it's ambiguous
+    let mut assignments = up_to(candidates, 2);
+    match assignments.len() {
+        0 => Err("no valid assignment".into()),
+        1 => Ok(assignments.pop().unwrap()),
+        _ => Err("assignment is ambiguous".into()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn candidates(spec: &[(&'static str, &[u32])]) -> Candidates<&'static str, u32> {
+        spec.iter()
+            .map(|&(k, vs)| (k, vs.iter().copied().collect()))
+            .collect()
+    }
+
+    #[test]
+    fn test_elimination() {
+        let mut c = candidates(&[("a", &[1]), ("b", &[1, 2]), ("c", &[1, 2, 3])]);
+        assert!(!eliminate(&mut c));
+        assert_eq!(c, candidates(&[("a", &[1]), ("b", &[2]), ("c", &[3])]));
+
+        let mut c = candidates(&[("a", &[1]), ("b", &[1])]);
+        assert!(eliminate(&mut c));
+    }
+
+    #[test]
+    fn test_matching_fallback() {
+        // elimination makes no progress, but c can only ever take 3
+        let c = candidates(&[("a", &[1, 2]), ("b", &[1, 2]), ("c", &[1, 2, 3])]);
+        let assignments = all(&c);
+        assert_eq!(assignments.len(), 2);
+        assert!(assignments.iter().all(|a| a["c"] == 3));
+    }
+
+    #[test]
+    fn test_ambiguous_and_impossible() {
+        let c = candidates(&[("a", &[1, 2]), ("b", &[1, 2]), ("c", &[3, 4])]);
+        assert_eq!(all(&c).len(), 4);
+        assert_eq!(up_to(&c, 3).len(), 3);
+        assert!(unique(&c).is_err());
+
+        // 12 interchangeable keys have 12! assignments, far too many to list
+        let wide = (0..12).map(|k| (k, (0..12).collect())).collect();
+        assert_eq!(up_to(&wide, 2).len(), 2);
+        assert!(unique(&wide).is_err());
+
+        let c = candidates(&[("a", &[1, 2]), ("b", &[1, 2]), ("c", &[1, 2])]);
+        assert!(all(&c).is_empty());
+        assert!(unique(&c).is_err());
+    }
+}
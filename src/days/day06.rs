@@ -1,11 +1,132 @@
 use crate::prelude::*;
 
-use std::collections::HashSet;
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct Answers(u32);
+
+impl Answers {
+    pub const ALL: Self = Self((1 << 26) - 1);
+
+    pub fn len(self) -> usize {
+        self.0.count_ones() as usize
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn contains(self, question: char) -> bool {
+        match Self::bit(question) {
+            Some(bit) => self.0 & bit != 0,
+            None => false,
+        }
+    }
+
+    pub fn questions(self) -> impl Iterator<Item = char> {
+        ('a'..='z').filter(move |&q| self.contains(q))
+    }
+
+    fn bit(question: char) -> Option<u32> {
+        if question.is_ascii_lowercase() {
+            Some(1 << (question as u8 - b'a'))
+        } else {
+            None
+        }
+    }
+}
+
+impl std::ops::BitOr for Answers {
+    type Output = Self;
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+impl std::ops::BitAnd for Answers {
+    type Output = Self;
+    fn bitand(self, rhs: Self) -> Self {
+        Self(self.0 & rhs.0)
+    }
+}
+
+impl std::ops::Not for Answers {
+    type Output = Self;
+    fn not(self) -> Self {
+        Self(!self.0 & Self::ALL.0)
+    }
+}
+
+impl FromStr for Answers {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.chars()
+            .map(|c| Self::bit(c).ok_or("question must be in a-z"))
+            .try_fold(Self::default(), |acc, bit| Ok(Self(acc.0 | bit?)))
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Group(Vec<Answers>);
+
+impl Group {
+    pub fn people(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn any(&self) -> Answers {
+        self.0.iter().fold(Answers::default(), |a, &b| a | b)
+    }
+
+    /// The questions everyone answered; nobody answered anything in an empty group.
+    pub fn all(&self) -> Answers {
+        self.0
+            .iter()
+            .copied()
+            .reduce(|a, b| a & b)
+            .unwrap_or_default()
+    }
+
+    pub fn none(&self) -> Answers {
+        !self.any()
+    }
+
+    pub fn histogram(&self) -> [usize; 26] {
+        let mut counts = [0; 26];
+        for person in &self.0 {
+            for q in person.questions() {
+                counts[(q as u8 - b'a') as usize] += 1;
+            }
+        }
+        counts
+    }
+
+    pub fn answered_by(&self, pred: impl Fn(usize) -> bool) -> Answers {
+        let counts = self.histogram();
+        (0..26)
+            .filter(|&i| pred(counts[i]))
+            .fold(Answers::default(), |acc, i| acc | Answers(1 << i))
+    }
+
+    pub fn exactly(&self, k: usize) -> Answers {
+        self.answered_by(|n| n == k)
+    }
+
+    pub fn majority(&self) -> Answers {
+        let people = self.people();
+        self.answered_by(|n| 2 * n > people)
+    }
+}
+
+impl std::iter::FromIterator<Answers> for Group {
+    fn from_iter<I: IntoIterator<Item = Answers>>(iter: I) -> Self {
+        Self(iter.into_iter().collect())
+    }
+}
 
 pub enum Day06 {}
 
 impl Challenge for Day06 {
-    type Input = Vec<Vec<HashSet<char>>>;
+    type Input = Vec<Group>;
     type Output1 = usize;
     type Output2 = usize;
 
@@ -14,29 +135,24 @@ impl Challenge for Day06 {
         let mut group = Vec::new();
         for line in data.lines() {
             let line = line?;
-            if line.is_empty() {
-                all.push(group);
-                group = Vec::new();
-            } else {
-                group.push(line.chars().collect());
+            if !line.is_empty() {
+                group.push(line.parse()?);
+            } else if !group.is_empty() {
+                all.push(Group(std::mem::take(&mut group)));
             }
         }
-        all.push(group);
+        if !group.is_empty() {
+            all.push(Group(group));
+        }
         Ok(all)
     }
 
     fn part1(input: Self::Input) -> Self::Output1 {
-        input
-            .into_iter()
-            .map(|group| group.into_iter().flatten().collect::<HashSet<_>>().len())
-            .sum()
+        input.iter().map(|group| group.any().len()).sum()
     }
 
     fn part2(input: Self::Input) -> Self::Output2 {
-        input
-            .into_iter()
-            .map(|group| group.into_iter().fold1(|a, b| &a & &b).unwrap().len())
-            .sum()
+        input.iter().map(|group| group.all().len()).sum()
     }
 }
 
@@ -53,7 +169,7 @@ mod test {
             &["b"],
         ];
         x.iter()
-            .map(|g| g.iter().map(|m| m.chars().collect()).collect())
+            .map(|g| g.iter().map(|m| m.parse().unwrap()).collect())
             .collect()
     }
 
@@ -66,4 +182,18 @@ mod test {
     fn test_day06_part2() {
         assert_eq!(Day06::part2(sample_input()), 6);
     }
+
+    #[test]
+    fn test_day06_queries() {
+        let group = &sample_input()[2];
+        assert_eq!(group.exactly(1).questions().collect::<String>(), "bc");
+        assert_eq!(group.majority().questions().collect::<String>(), "a");
+        assert_eq!(group.none().len(), 23);
+        assert_eq!(group.histogram()[..4], [2, 1, 1, 0]);
+        assert!("aB".parse::<Answers>().is_err());
+
+        let empty = Group::default();
+        assert!(empty.all().is_empty());
+        assert!(empty.any().is_empty());
+    }
 }
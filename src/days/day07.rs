@@ -2,11 +2,11 @@ use crate::prelude::*;
 
 use std::collections::{HashMap, HashSet};
 
-#[derive(Debug, Hash, Clone, PartialEq, Eq)]
+#[derive(Debug, Hash, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Color(String, String);
 
 impl Color {
-    fn new(a: impl Into<String>, b: impl Into<String>) -> Self {
+    pub fn new(a: impl Into<String>, b: impl Into<String>) -> Self {
         Self(a.into(), b.into())
     }
 
@@ -19,6 +19,12 @@ impl Color {
     }
 }
 
+impl std::fmt::Display for Color {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", self.0, self.1)
+    }
+}
+
 type Rule = (Color, Vec<SomeBags>);
 
 #[derive(Debug, Clone)]
@@ -29,7 +35,7 @@ pub struct SomeBags {
 
 fn parse_rule(s: &str) -> Result<Rule, Error> {
     let mut words = s.split(' ');
-    let color = Color::from_iter(&mut words).unwrap();
+    let color = Color::from_iter(&mut words).ok_or("malformed color")?;
     words.next(); // "bags"
     words.next(); // "contain"
     let mut contents = Vec::new();
@@ -39,74 +45,133 @@ fn parse_rule(s: &str) -> Result<Rule, Error> {
         }
         contents.push(SomeBags {
             amount: n.parse()?,
-            color: Color::from_iter(&mut words).unwrap(),
+            color: Color::from_iter(&mut words).ok_or("malformed color")?,
         });
         words.next(); // "bags"
     }
     Ok((color, contents))
 }
 
-pub enum Day07 {}
-
-impl Challenge for Day07 {
-    type Input = HashMap<Color, Vec<SomeBags>>;
-    type Output1 = usize;
-    type Output2 = usize;
+#[derive(Debug, Clone, Default)]
+pub struct BagGraph {
+    contents: HashMap<Color, Vec<SomeBags>>,
+    containers: HashMap<Color, Vec<Color>>,
+}
 
-    fn read(data: File) -> Result<Self::Input, Error> {
-        let mut rules = HashMap::new();
-        for line in data.lines() {
-            let line = line?;
-            let (parent, children) = parse_rule(&line)?;
-            rules.insert(parent, children);
+impl BagGraph {
+    pub fn insert(&mut self, (parent, children): Rule) {
+        for child in &children {
+            self.containers
+                .entry(child.color.clone())
+                .or_default()
+                .push(parent.clone());
         }
-        Ok(rules)
+        self.contents.insert(parent, children);
     }
 
-    fn part1(input: Self::Input) -> Self::Output1 {
-        let mut ps = HashSet::new();
-        ps.insert(Color::new("shiny", "gold"));
-        loop {
-            let mut should_break = true;
-            for (color, contents) in &input {
-                if ps.contains(color) {
-                    continue;
-                }
+    pub fn contents(&self, color: &Color) -> &[SomeBags] {
+        self.contents.get(color).map_or(&[], Vec::as_slice)
+    }
+
+    pub fn containers(&self, color: &Color) -> &[Color] {
+        self.containers.get(color).map_or(&[], Vec::as_slice)
+    }
 
-                for child in contents {
-                    if ps.contains(&child.color) {
-                        ps.insert(color.clone());
-                        should_break = false;
-                        break;
-                    }
+    pub fn can_contain(&self, target: &Color) -> HashSet<&Color> {
+        let mut seen = HashSet::new();
+        let mut stack = vec![target];
+        while let Some(color) = stack.pop() {
+            for parent in self.containers(color) {
+                if seen.insert(parent) {
+                    stack.push(parent);
                 }
             }
-            if should_break {
-                break;
-            }
         }
-        ps.len() - 1
+        seen
     }
 
-    fn part2(input: Self::Input) -> Self::Output2 {
+    pub fn count_inside(&self, target: &Color) -> Result<usize, Error> {
+        let mut memo = HashMap::new();
+        let mut path = Vec::new();
+        self.count_inside_rec(target, &mut memo, &mut path)
+            .map_err(|cycle| Error::Cycle(cycle.iter().join(" -> ")))
+    }
+
+    fn count_inside_rec<'a>(
+        &'a self,
+        color: &'a Color,
+        memo: &mut HashMap<&'a Color, usize>,
+        path: &mut Vec<&'a Color>,
+    ) -> Result<usize, Vec<Color>> {
+        if let Some(&n) = memo.get(color) {
+            return Ok(n);
+        }
+        if let Some(i) = path.iter().position(|&c| c == color) {
+            return Err(path[i..].iter().map(|&c| c.clone()).collect());
+        }
+
+        path.push(color);
         let mut n = 0;
-        let mut bags = vec![SomeBags {
-            amount: 1,
-            color: Color::new("shiny", "gold"),
-        }];
-
-        while let Some(bag) = bags.pop() {
-            n += bag.amount;
-            for child in &input[&bag.color] {
-                let multiplied_child = SomeBags {
-                    amount: bag.amount * child.amount,
-                    color: child.color.clone(),
-                };
-                bags.push(multiplied_child);
+        for child in self.contents(color) {
+            n += child.amount * (1 + self.count_inside_rec(&child.color, memo, path)?);
+        }
+        path.pop();
+
+        memo.insert(color, n);
+        Ok(n)
+    }
+
+    pub fn find_cycle(&self) -> Option<Vec<Color>> {
+        let mut memo = HashMap::new();
+        let mut path = Vec::new();
+        self.contents
+            .keys()
+            .find_map(|color| self.count_inside_rec(color, &mut memo, &mut path).err())
+    }
+
+    pub fn to_dot(&self) -> String {
+        let mut out = String::from("digraph bags {\n");
+        for (parent, children) in self.contents.iter().sorted_by_key(|(c, _)| *c) {
+            out += &format!("    \"{}\";\n", parent);
+            for child in children {
+                out += &format!(
+                    "    \"{}\" -> \"{}\" [label={}];\n",
+                    parent, child.color, child.amount
+                );
             }
         }
+        out += "}\n";
+        out
+    }
+}
+
+impl std::iter::FromIterator<Rule> for BagGraph {
+    fn from_iter<I: IntoIterator<Item = Rule>>(iter: I) -> Self {
+        let mut graph = Self::default();
+        for rule in iter {
+            graph.insert(rule);
+        }
+        graph
+    }
+}
+
+pub enum Day07 {}
+
+impl Challenge for Day07 {
+    type Input = BagGraph;
+    type Output1 = usize;
+    type Output2 = usize;
+
+    fn read(data: File) -> Result<Self::Input, Error> {
+        data.lines().map(|line| parse_rule(&line?)).collect()
+    }
 
-        n - 1
+    fn part1(input: Self::Input) -> Self::Output1 {
+        input.can_contain(&Color::new("shiny", "gold")).len()
+    }
+
+    fn part2(input: Self::Input) -> Self::Output2 {
+        input.count_inside(&Color::new("shiny", "gold")).unwrap()
     }
 }
 
@@ -126,7 +191,7 @@ mod test {
             "faded blue bags contain no other bags.",
             "dotted black bags contain no other bags.",
         ];
-        s.iter().map(|s| parse_rule(*s).unwrap()).collect()
+        s.iter().map(|s| parse_rule(s).unwrap()).collect()
     }
 
     fn sample_input2() -> <Day07 as Challenge>::Input {
@@ -139,7 +204,7 @@ mod test {
             "dark blue bags contain 2 dark violet bags.",
             "dark violet bags contain no other bags.",
         ];
-        s.iter().map(|s| parse_rule(*s).unwrap()).collect()
+        s.iter().map(|s| parse_rule(s).unwrap()).collect()
     }
 
     #[test]
@@ -152,4 +217,33 @@ mod test {
         assert_eq!(Day07::part2(sample_input()), 32);
         assert_eq!(Day07::part2(sample_input2()), 126);
     }
+
+    #[test]
+    fn test_day07_other_targets() {
+        let graph = sample_input();
+        assert_eq!(graph.can_contain(&Color::new("faded", "blue")).len(), 7);
+        assert_eq!(graph.count_inside(&Color::new("dark", "olive")).unwrap(), 7);
+        assert_eq!(graph.find_cycle(), None);
+    }
+
+    #[test]
+    fn test_day07_cycle() {
+        let graph: BagGraph = [
+            "shiny gold bags contain 1 dark red bag.",
+            "dark red bags contain 2 shiny gold bags.",
+        ]
+        .iter()
+        .map(|s| parse_rule(s).unwrap())
+        .collect();
+        assert!(graph.count_inside(&Color::new("shiny", "gold")).is_err());
+        assert_eq!(graph.find_cycle().map(|c| c.len()), Some(2));
+        assert!(parse_rule("shiny").is_err());
+    }
+
+    #[test]
+    fn test_day07_dot() {
+        let dot = sample_input2().to_dot();
+        assert!(dot.starts_with("digraph bags {\n"));
+        assert!(dot.contains("\"shiny gold\" -> \"dark red\" [label=2];"));
+    }
 }
@@ -1,75 +1,118 @@
 use crate::prelude::*;
 
-pub enum Day09 {}
+use std::{collections::HashMap, ops::Range};
 
-impl Challenge for Day09 {
-    type Input = Vec<usize>;
-    type Output1 = usize;
-    type Output2 = usize;
+#[derive(Debug, Default)]
+struct Window(HashMap<i64, usize>);
 
-    fn read(data: File) -> Result<Self::Input, Error> {
-        data.parse_lines()
+impl Window {
+    fn insert(&mut self, n: i64) {
+        *self.0.entry(n).or_default() += 1;
     }
 
-    fn part1(input: Self::Input) -> Self::Output1 {
-        'test: for i in 25..input.len() {
-            let predecessors = (i - 25)..i;
-            for (j, k) in predecessors.tuple_combinations() {
-                if input[j] + input[k] == input[i] {
-                    continue 'test;
-                }
+    fn remove(&mut self, n: i64) {
+        if let Some(count) = self.0.get_mut(&n) {
+            *count -= 1;
+            if *count == 0 {
+                self.0.remove(&n);
             }
-            return input[i];
         }
-        panic!("all numbers seem valid")
     }
 
-    fn part2(input: Self::Input) -> Self::Output2 {
-        let target = Self::part1(input.clone());
-        let mut range = 0..0;
-
-        macro_rules! sum {
-            (.) => {
-                input[range.clone()].iter().copied()
-            };
-            () => {
-                sum!(.).sum::<usize>()
-            };
+    fn has_pair_summing_to(&self, target: i64) -> bool {
+        self.0.iter().any(|(&a, &count)| {
+            let b = target - a;
+            if a == b {
+                count >= 2
+            } else {
+                self.0.contains_key(&b)
+            }
+        })
+    }
+}
+
+pub fn first_invalid(numbers: &[i64], preamble: usize) -> Option<i64> {
+    let mut window = Window::default();
+    for &n in numbers.iter().take(preamble) {
+        window.insert(n);
+    }
+    for i in preamble..numbers.len() {
+        if !window.has_pair_summing_to(numbers[i]) {
+            return Some(numbers[i]);
         }
+        window.remove(numbers[i - preamble]);
+        window.insert(numbers[i]);
+    }
+    None
+}
 
-        loop {
-            while sum!() < target {
-                range.end += 1;
-            }
-            while sum!() > target {
-                range.start += 1;
-            }
-            if sum!() == target {
-                break;
-            }
+/// Finds a run of at least two numbers summing to `target`, preferring the one that ends first.
+pub fn contiguous_range(numbers: &[i64], target: i64) -> Option<Range<usize>> {
+    let mut prefix_sums = vec![0];
+    for &n in numbers {
+        prefix_sums.push(prefix_sums.last().unwrap() + n);
+    }
+
+    let mut earliest = HashMap::new();
+    for end in 2..prefix_sums.len() {
+        earliest.entry(prefix_sums[end - 2]).or_insert(end - 2);
+        if let Some(&start) = earliest.get(&(prefix_sums[end] - target)) {
+            return Some(start..end);
         }
+    }
+    None
+}
+
+pub enum Day09<const PREAMBLE: usize = 25> {}
+
+impl<const PREAMBLE: usize> Challenge for Day09<PREAMBLE> {
+    type Input = Vec<i64>;
+    type Output1 = i64;
+    type Output2 = i64;
 
-        sum!(.).min().unwrap() + sum!(.).max().unwrap()
+    fn read(data: File) -> Result<Self::Input, Error> {
+        data.parse_lines()
+    }
+
+    fn part1(input: Self::Input) -> Self::Output1 {
+        first_invalid(&input, PREAMBLE).expect("all numbers seem valid")
+    }
+
+    fn part2(input: Self::Input) -> Self::Output2 {
+        let target = first_invalid(&input, PREAMBLE).expect("all numbers seem valid");
+        let range = contiguous_range(&input, target).expect("no contiguous range found");
+        let (min, max) = input[range].iter().minmax().into_option().unwrap();
+        min + max
     }
 }
 
-/*
 #[cfg(test)]
 mod test {
     use super::*;
 
     fn sample_input() -> <Day09 as Challenge>::Input {
-        vec![]
+        vec![
+            35, 20, 15, 25, 47, 40, 62, 55, 65, 95, 102, 117, 150, 182, 127, 219, 299, 277, 309,
+            576,
+        ]
     }
 
     #[test]
     fn test_day09_part1() {
-        assert_eq!(Day09::part1(sample_input()), ());
+        assert_eq!(Day09::<5>::part1(sample_input()), 127);
     }
 
     #[test]
     fn test_day09_part2() {
-        assert_eq!(Day09::part2(sample_input()), ());
+        assert_eq!(Day09::<5>::part2(sample_input()), 62);
+        assert_eq!(contiguous_range(&sample_input(), 127), Some(2..6));
+    }
+
+    #[test]
+    fn test_day09_nonpositive() {
+        assert_eq!(first_invalid(&[3, 3, 6, 7], 2), Some(7));
+        assert_eq!(contiguous_range(&[5, -3, 0, 4, 2], 1), Some(1..4));
+        assert_eq!(contiguous_range(&[5, 0, 9], 5), Some(0..2));
+        assert_eq!(contiguous_range(&[5, 9], 5), None);
     }
 }
-*/
@@ -1,11 +1,77 @@
 use crate::prelude::*;
 
+pub struct Arrangements {
+    joltages: Vec<u32>,
+    max_jump: u32,
+    ways: Vec<u128>,
+}
+
+impl Arrangements {
+    pub fn new(adapters: &[u32], max_jump: u32) -> Result<Self, Error> {
+        let mut joltages = adapters.to_vec();
+        joltages.push(0);
+        joltages.sort_unstable();
+
+        // ways[i] is the number of chains from joltages[i] to the device
+        let mut ways = vec![0_u128; joltages.len()];
+        ways[joltages.len() - 1] = 1;
+        for i in (0..joltages.len() - 1).rev() {
+            for j in (i + 1..joltages.len()).take_while(|&j| joltages[j] - joltages[i] <= max_jump)
+            {
+                ways[i] = ways[i]
+                    .checked_add(ways[j])
+                    .ok_or("arrangement count overflowed")?;
+            }
+        }
+
+        Ok(Self {
+            joltages,
+            max_jump,
+            ways,
+        })
+    }
+
+    pub fn count(&self) -> u128 {
+        self.ways[0]
+    }
+
+    fn successors(&self, i: usize) -> impl Iterator<Item = usize> + '_ {
+        (i + 1..self.joltages.len())
+            .take_while(move |&j| self.joltages[j] - self.joltages[i] <= self.max_jump)
+    }
+
+    /// The `index`th valid chain in lexicographic order, excluding the outlet and device.
+    pub fn nth(&self, mut index: u128) -> Option<Vec<u32>> {
+        if index >= self.count() {
+            return None;
+        }
+
+        let mut chain = Vec::new();
+        let mut i = 0;
+        while i != self.joltages.len() - 1 {
+            for j in self.successors(i) {
+                if index < self.ways[j] {
+                    i = j;
+                    break;
+                }
+                index -= self.ways[j];
+            }
+            chain.push(self.joltages[i]);
+        }
+        Some(chain)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Vec<u32>> + '_ {
+        (0..self.count()).map(move |i| self.nth(i).unwrap())
+    }
+}
+
 pub enum Day10 {}
 
 impl Challenge for Day10 {
     type Input = Vec<u32>;
     type Output1 = u32;
-    type Output2 = u64;
+    type Output2 = u128;
 
     fn read(data: File) -> Result<Self::Input, Error> {
         data.parse_lines()
@@ -29,37 +95,8 @@ impl Challenge for Day10 {
         diff1 * diff3
     }
 
-    fn part2(mut input: Self::Input) -> Self::Output2 {
-        input.push(0);
-        input.sort();
-
-        // THIS IS THE SNEAKY PART
-        assert!(input.iter().tuple_windows().all(|(a, b)| b - a != 2));
-
-        let lookup = [
-            // can't omit anything
-            1, 1, 1, // can omit b from abc or can choose not to
-            2, // can omit nothing, b, c, or bc from abcd
-            4,
-            // can omit any combination except bcd from abcde
-            // (because then there'd be a joltage gap of 4)
-            7,
-            // further group sizes unhandled; would panic
-        ];
-
-        let mut n = 1;
-
-        let mut group_size = 1;
-        for i in 1..input.len() {
-            if input[i] - input[i - 1] == 3 {
-                n *= lookup[group_size];
-                group_size = 0;
-            }
-            group_size += 1;
-        }
-        n *= lookup[group_size]; // the final group
-
-        n
+    fn part2(input: Self::Input) -> Self::Output2 {
+        Arrangements::new(&input, 3).unwrap().count()
     }
 }
 
@@ -89,4 +126,22 @@ mod test {
         assert_eq!(Day10::part2(sample_input()), 8);
         assert_eq!(Day10::part2(sample_input2()), 19208);
     }
+
+    #[test]
+    fn test_day10_chains() {
+        let arrangements = Arrangements::new(&sample_input(), 3).unwrap();
+        let chains = arrangements.iter().collect::<Vec<_>>();
+        assert_eq!(chains.len(), 8);
+        assert_eq!(chains[0], vec![1, 4, 5, 6, 7, 10, 11, 12, 15, 16, 19]);
+        assert_eq!(chains[7], vec![1, 4, 7, 10, 12, 15, 16, 19]);
+        assert_eq!(arrangements.nth(8), None);
+    }
+
+    #[test]
+    fn test_day10_gaps() {
+        assert_eq!(Arrangements::new(&[1, 3, 5], 3).unwrap().count(), 2);
+        assert_eq!(Arrangements::new(&[1, 6], 3).unwrap().count(), 0);
+        assert_eq!(Arrangements::new(&[2, 4, 6, 8, 10], 4).unwrap().count(), 8);
+        assert!(Arrangements::new(&(1..400).collect::<Vec<_>>(), 3).is_err());
+    }
 }
@@ -1,10 +1,10 @@
 use crate::prelude::*;
 
-use std::ops::Add;
+use std::{collections::HashMap, convert::TryFrom, fmt};
 
 use itertools::iproduct;
 
-#[derive(Copy, Clone, PartialEq, Eq)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 #[repr(u8)]
 pub enum State {
     Empty = b'L',
@@ -12,86 +12,187 @@ pub enum State {
     Floor = b'.',
 }
 
-impl State {
-    fn from_char(c: char) -> Self {
+impl TryFrom<char> for State {
+    type Error = Error;
+
+    fn try_from(c: char) -> Result<Self, Self::Error> {
         match c {
-            '.' => Self::Floor,
-            'L' => Self::Empty,
-            '#' => Self::Occupied,
-            _ => panic!(),
+            '.' => Ok(Self::Floor),
+            'L' => Ok(Self::Empty),
+            '#' => Ok(Self::Occupied),
+            _ => Err("unknown seat character".into()),
         }
     }
 }
 
-fn generation(prev: &[Vec<State>]) -> (Vec<Vec<State>>, bool) {
-    let mut did_change = false;
-    let mut new = prev.to_owned();
-    for (x, y) in iproduct!(0..prev[0].len(), 0..prev.len()) {
-        let seat = &mut new[y][x];
-        if *seat == State::Floor {
-            continue;
-        }
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Neighbourhood {
+    Adjacent,
+    LineOfSight { max_distance: Option<usize> },
+}
 
-        let (min_x, min_y) = (x.saturating_sub(1), y.saturating_sub(1));
-        let (max_x, max_y) = (
-            x.add(1).min(prev[0].len() - 1),
-            y.add(1).min(prev.len() - 1),
-        );
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Rules {
+    pub neighbourhood: Neighbourhood,
+    /// An empty seat fills up if at most this many of its neighbours are occupied.
+    pub occupy_at_most: usize,
+    /// An occupied seat empties if at least this many of its neighbours are occupied.
+    pub vacate_at_least: usize,
+    pub wrap: bool,
+}
+
+impl Rules {
+    pub const PART1: Self = Self {
+        neighbourhood: Neighbourhood::Adjacent,
+        occupy_at_most: 0,
+        vacate_at_least: 4,
+        wrap: false,
+    };
+
+    pub const PART2: Self = Self {
+        neighbourhood: Neighbourhood::LineOfSight { max_distance: None },
+        occupy_at_most: 0,
+        vacate_at_least: 5,
+        wrap: false,
+    };
+}
 
-        let num_cells = iproduct!(min_x..=max_x, min_y..=max_y)
-            .map(|(x, y)| (prev[y][x] == State::Occupied) as u8)
-            .sum::<u8>();
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    Stable { generations: usize },
+    Oscillating { start: usize, period: usize },
+}
+
+pub struct Simulation {
+    width: usize,
+    cells: Vec<State>,
+    neighbours: Vec<Vec<usize>>,
+    rules: Rules,
+    generation: usize,
+}
+
+impl Simulation {
+    pub fn new(grid: &[Vec<State>], rules: Rules) -> Result<Self, Error> {
+        check_grid(grid)?;
+        let (width, height) = (grid.first().map_or(0, Vec::len), grid.len());
+        let cells = grid.concat();
 
-        if *seat == State::Empty && num_cells == 0 {
-            *seat = State::Occupied;
-            did_change = true;
-        } else if *seat == State::Occupied && num_cells >= 5 {
-            *seat = State::Empty;
-            did_change = true;
+        let max_distance = match rules.neighbourhood {
+            Neighbourhood::Adjacent => Some(1),
+            Neighbourhood::LineOfSight { max_distance } => max_distance,
+        };
+
+        let mut neighbours = vec![Vec::new(); cells.len()];
+        for (x, y) in iproduct!(0..width, 0..height) {
+            if cells[y * width + x] == State::Floor {
+                continue;
+            }
+            for (dx, dy) in iproduct!(-1..=1, -1..=1).filter(|v| *v != (0, 0)) {
+                let (mut i, mut j) = (x as isize, y as isize);
+                for _ in 0..max_distance.unwrap_or(width * height) {
+                    i += dx;
+                    j += dy;
+                    if rules.wrap {
+                        i = i.rem_euclid(width as isize);
+                        j = j.rem_euclid(height as isize);
+                    } else if i < 0 || j < 0 || i >= width as isize || j >= height as isize {
+                        break;
+                    }
+                    if (i, j) == (x as isize, y as isize) {
+                        break;
+                    }
+
+                    let idx = j as usize * width + i as usize;
+                    if cells[idx] != State::Floor {
+                        neighbours[y * width + x].push(idx);
+                        break;
+                    }
+                }
+            }
         }
+
+        Ok(Self {
+            width,
+            cells,
+            neighbours,
+            rules,
+            generation: 0,
+        })
     }
-    (new, did_change)
-}
 
-fn gen2(prev: &[Vec<State>]) -> (Vec<Vec<State>>, bool) {
-    let mut did_change = false;
-    let mut new = prev.to_owned();
-    for (x, y) in iproduct!(0..prev[0].len(), 0..prev.len()) {
-        let seat = &mut new[y][x];
-        if *seat == State::Floor {
-            continue;
+    pub fn generation(&self) -> usize {
+        self.generation
+    }
+
+    pub fn occupied(&self) -> usize {
+        self.cells.iter().filter(|&&s| s == State::Occupied).count()
+    }
+
+    pub fn step(&mut self) -> bool {
+        let mut new = self.cells.clone();
+        let mut did_change = false;
+        for (i, seat) in new.iter_mut().enumerate() {
+            let num_occupied = self.neighbours[i]
+                .iter()
+                .filter(|&&n| self.cells[n] == State::Occupied)
+                .count();
+
+            if *seat == State::Empty && num_occupied <= self.rules.occupy_at_most {
+                *seat = State::Occupied;
+                did_change = true;
+            } else if *seat == State::Occupied && num_occupied >= self.rules.vacate_at_least {
+                *seat = State::Empty;
+                did_change = true;
+            }
+        }
+        self.cells = new;
+        if did_change {
+            self.generation += 1;
         }
+        did_change
+    }
 
-        let mut num_cells = 0;
-        for (dx, dy) in iproduct!(-1..=1, -1..=1).filter(|v| *v != (0, 0)) {
-            for r in 1.. {
-                if (r > x && dx < 0) || (r + x >= prev[0].len() && dx > 0) {
-                    break;
-                } else if (r > y && dy < 0) || (r + y >= prev.len() && dy > 0) {
-                    break;
-                }
-                let i = x as isize + r as isize * dx;
-                let j = y as isize + r as isize * dy;
-
-                let s = prev[j as usize][i as usize];
-                if s == State::Occupied {
-                    num_cells += 1;
-                    break;
-                } else if s == State::Empty {
-                    break;
-                }
+    pub fn run(&mut self) -> Outcome {
+        let mut seen = HashMap::new();
+        loop {
+            seen.insert(self.cells.clone(), self.generation);
+            if !self.step() {
+                return Outcome::Stable {
+                    generations: self.generation,
+                };
+            }
+            if let Some(&start) = seen.get(&self.cells) {
+                return Outcome::Oscillating {
+                    start,
+                    period: self.generation - start,
+                };
             }
         }
+    }
+}
 
-        if *seat == State::Empty && num_cells == 0 {
-            *seat = State::Occupied;
-            did_change = true;
-        } else if *seat == State::Occupied && num_cells >= 5 {
-            *seat = State::Empty;
-            did_change = true;
+impl fmt::Display for Simulation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for row in self.cells.chunks(self.width.max(1)) {
+            for seat in row {
+                write!(f, "{}", *seat as u8 as char)?;
+            }
+            writeln!(f)?;
         }
+        Ok(())
+    }
+}
+
+fn parse_row(line: &str) -> Result<Vec<State>, Error> {
+    line.chars().map(State::try_from).collect()
+}
+
+fn check_grid(grid: &[Vec<State>]) -> Result<(), Error> {
+    if grid.iter().map(Vec::len).all_equal() {
+        Ok(())
+    } else {
+        Err("rows have different lengths".into())
     }
-    (new, did_change)
 }
 
 pub enum Day11 {}
@@ -102,52 +203,24 @@ impl Challenge for Day11 {
     type Output2 = usize;
 
     fn read(data: File) -> Result<Self::Input, Error> {
-        let mut rows = Vec::new();
-        for line in data.lines() {
-            rows.push(line?.chars().map(State::from_char).collect());
-        }
-        Ok(rows)
+        let grid: Self::Input = data
+            .lines()
+            .map(|line| parse_row(&line?))
+            .collect::<Result<_, _>>()?;
+        check_grid(&grid)?;
+        Ok(grid)
     }
 
     fn part1(input: Self::Input) -> Self::Output1 {
-        let mut gen = input;
-        loop {
-            let (new_gen, did_change) = generation(&gen);
-            gen = new_gen;
-            if !did_change {
-                break;
-            }
-        }
-        gen.into_iter()
-            .flatten()
-            .filter(|x| *x == State::Occupied)
-            .count()
+        let mut sim = Simulation::new(&input, Rules::PART1).unwrap();
+        sim.run();
+        sim.occupied()
     }
 
     fn part2(input: Self::Input) -> Self::Output2 {
-        let mut gen = input;
-        loop {
-            let (new_gen, did_change) = gen2(&gen);
-            gen = new_gen;
-
-            /*
-            for row in &gen {
-                for seat in row {
-                    print!("{}", *seat as u8 as char);
-                }
-                println!();
-            }
-            println!();
-            */
-
-            if !did_change {
-                break;
-            }
-        }
-        gen.into_iter()
-            .flatten()
-            .filter(|x| *x == State::Occupied)
-            .count()
+        let mut sim = Simulation::new(&input, Rules::PART2).unwrap();
+        sim.run();
+        sim.occupied()
     }
 }
 
@@ -170,7 +243,7 @@ mod test {
         ]
         .iter()
         .copied()
-        .map(|line| line.chars().map(State::from_char).collect())
+        .map(|line| parse_row(line).unwrap())
         .collect()
     }
 
@@ -183,4 +256,53 @@ mod test {
     fn test_day11_part2() {
         assert_eq!(Day11::part2(sample_input()), 26);
     }
+
+    #[test]
+    fn test_day11_ragged() {
+        assert!(check_grid(&sample_input()).is_ok());
+        let grid = [parse_row("L.L").unwrap(), parse_row("LL").unwrap()];
+        assert!(check_grid(&grid).is_err());
+        assert!(Simulation::new(&grid, Rules::PART1).is_err());
+    }
+
+    #[test]
+    fn test_day11_generations() {
+        let mut sim = Simulation::new(&sample_input(), Rules::PART1).unwrap();
+        assert_eq!(sim.run(), Outcome::Stable { generations: 5 });
+        let mut sim = Simulation::new(&sample_input(), Rules::PART2).unwrap();
+        assert_eq!(sim.run(), Outcome::Stable { generations: 6 });
+    }
+
+    #[test]
+    fn test_day11_custom_rules() {
+        let rules = Rules {
+            neighbourhood: Neighbourhood::Adjacent,
+            occupy_at_most: 0,
+            vacate_at_least: 1,
+            wrap: false,
+        };
+        let mut sim = Simulation::new(&[parse_row("LL").unwrap()], rules).unwrap();
+        assert_eq!(
+            sim.run(),
+            Outcome::Oscillating {
+                start: 0,
+                period: 2
+            }
+        );
+
+        let rules = Rules {
+            neighbourhood: Neighbourhood::LineOfSight {
+                max_distance: Some(2),
+            },
+            wrap: true,
+            ..rules
+        };
+        let grid = [".....", "L..L.", "....."]
+            .iter()
+            .map(|line| parse_row(line).unwrap())
+            .collect::<Vec<_>>();
+        let sim = Simulation::new(&grid, rules).unwrap();
+        assert_eq!(sim.neighbours[5], vec![8]);
+        assert!(parse_row("L?").is_err());
+    }
 }
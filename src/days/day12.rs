@@ -1,41 +1,6 @@
 use crate::prelude::*;
 
-#[derive(Copy, Clone)]
-enum Direction {
-    North,
-    South,
-    East,
-    West,
-}
-
-impl Direction {
-    fn left(self) -> Self {
-        match self {
-            Self::North => Self::West,
-            Self::West => Self::South,
-            Self::South => Self::East,
-            Self::East => Self::North,
-        }
-    }
-
-    fn right(self) -> Self {
-        match self {
-            Self::North => Self::East,
-            Self::East => Self::South,
-            Self::South => Self::West,
-            Self::West => Self::North,
-        }
-    }
-
-    fn vec(self) -> (i64, i64) {
-        match self {
-            Self::North => (0, -1),
-            Self::East => (1, 0),
-            Self::South => (0, 1),
-            Self::West => (-1, 0),
-        }
-    }
-}
+use std::fmt::Write;
 
 #[derive(Copy, Clone)]
 enum Action {
@@ -58,25 +23,172 @@ impl FromStr for Instruction {
     type Err = Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let action = match s.nth_char(0) {
-            'N' => Action::North,
-            'S' => Action::South,
-            'E' => Action::East,
-            'W' => Action::West,
-            'L' => Action::Left,
-            'R' => Action::Right,
-            'F' => Action::Forward,
+        let action = match s.chars().next() {
+            Some('N') => Action::North,
+            Some('S') => Action::South,
+            Some('E') => Action::East,
+            Some('W') => Action::West,
+            Some('L') => Action::Left,
+            Some('R') => Action::Right,
+            Some('F') => Action::Forward,
             _ => Err("bad action")?,
         };
 
-        let mut amount = s[1..].parse()?;
+        let mut amount: i64 = s[1..].parse()?;
         if matches!(action, Action::Left | Action::Right) {
-            amount /= 90;
+            if amount % 90 != 0 {
+                Err("turn angle must be a multiple of 90 degrees")?;
+            }
+            amount = (amount / 90).rem_euclid(4);
         }
         Ok(Self { action, amount })
     }
 }
 
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Mode {
+    /// Cardinal actions move the ship; the waypoint is just its heading.
+    Heading,
+    /// Cardinal actions move the waypoint.
+    Waypoint,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Snapshot {
+    pub ship: (i64, i64),
+    /// Relative to the ship.
+    pub waypoint: (i64, i64),
+}
+
+pub struct Ferry {
+    mode: Mode,
+    trajectory: Vec<Snapshot>,
+}
+
+impl Ferry {
+    pub fn new(mode: Mode) -> Self {
+        let waypoint = match mode {
+            Mode::Heading => (1, 0),
+            Mode::Waypoint => (10, -1),
+        };
+        Self {
+            mode,
+            trajectory: vec![Snapshot {
+                ship: (0, 0),
+                waypoint,
+            }],
+        }
+    }
+
+    pub fn navigate(mode: Mode, instructions: &[Instruction]) -> Self {
+        let mut ferry = Self::new(mode);
+        for &instruction in instructions {
+            ferry.apply(instruction);
+        }
+        ferry
+    }
+
+    pub fn position(&self) -> Snapshot {
+        *self.trajectory.last().unwrap()
+    }
+
+    pub fn trajectory(&self) -> &[Snapshot] {
+        &self.trajectory
+    }
+
+    pub fn distance(&self) -> i64 {
+        let (x, y) = self.position().ship;
+        x.abs() + y.abs()
+    }
+
+    pub fn apply(&mut self, instruction: Instruction) {
+        let Snapshot {
+            mut ship,
+            mut waypoint,
+        } = self.position();
+        let amt = instruction.amount;
+
+        let target = match self.mode {
+            Mode::Heading => &mut ship,
+            Mode::Waypoint => &mut waypoint,
+        };
+        match instruction.action {
+            Action::North => target.1 -= amt,
+            Action::South => target.1 += amt,
+            Action::East => target.0 += amt,
+            Action::West => target.0 -= amt,
+            Action::Left => waypoint = rotate_right(waypoint, 4 - amt),
+            Action::Right => waypoint = rotate_right(waypoint, amt),
+            Action::Forward => {
+                ship.0 += waypoint.0 * amt;
+                ship.1 += waypoint.1 * amt;
+            }
+        }
+
+        self.trajectory.push(Snapshot { ship, waypoint });
+    }
+
+    pub fn to_csv(&self) -> String {
+        let mut out = String::from("step,ship_x,ship_y,waypoint_x,waypoint_y\n");
+        for (i, s) in self.trajectory.iter().enumerate() {
+            writeln!(
+                out,
+                "{},{},{},{},{}",
+                i, s.ship.0, s.ship.1, s.waypoint.0, s.waypoint.1
+            )
+            .unwrap();
+        }
+        out
+    }
+
+    pub fn to_svg(&self) -> String {
+        let ships = self.trajectory.iter().map(|s| s.ship).collect::<Vec<_>>();
+        let waypoints = self
+            .trajectory
+            .iter()
+            .map(|s| (s.ship.0 + s.waypoint.0, s.ship.1 + s.waypoint.1))
+            .collect::<Vec<_>>();
+
+        let all = || ships.iter().chain(&waypoints);
+        let (min_x, max_x) = all().map(|p| p.0).minmax().into_option().unwrap();
+        let (min_y, max_y) = all().map(|p| p.1).minmax().into_option().unwrap();
+        let margin = ((max_x - min_x).max(max_y - min_y) / 20).max(1);
+
+        let polyline = |points: &[(i64, i64)], color: &str| {
+            let points = points.iter().map(|(x, y)| format!("{},{}", x, y)).join(" ");
+            format!(
+                "  <polyline points=\"{}\" fill=\"none\" stroke=\"{}\" stroke-width=\"{}\"/>\n",
+                points,
+                color,
+                margin as f64 / 4.0
+            )
+        };
+
+        let mut out = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"{} {} {} {}\">\n",
+            min_x - margin,
+            min_y - margin,
+            max_x - min_x + 2 * margin,
+            max_y - min_y + 2 * margin
+        );
+        if self.mode == Mode::Waypoint {
+            out += &polyline(&waypoints, "gray");
+        }
+        out += &polyline(&ships, "blue");
+        out += "</svg>\n";
+        out
+    }
+}
+
+fn rotate_right((x, y): (i64, i64), quarter_turns: i64) -> (i64, i64) {
+    match quarter_turns.rem_euclid(4) {
+        0 => (x, y),
+        1 => (-y, x),
+        2 => (-x, -y),
+        _ => (y, -x),
+    }
+}
+
 pub enum Day12 {}
 
 impl Challenge for Day12 {
@@ -89,60 +201,11 @@ impl Challenge for Day12 {
     }
 
     fn part1(input: Self::Input) -> Self::Output1 {
-        let mut bearing = Direction::East;
-        let mut x = 0;
-        let mut y = 0;
-        for instruction in input {
-            let amt = instruction.amount;
-            match instruction.action {
-                Action::North => y -= amt,
-                Action::South => y += amt,
-                Action::East => x += amt,
-                Action::West => x -= amt,
-                Action::Left => (0..amt).for_each(|_| bearing = bearing.left()),
-                Action::Right => (0..amt).for_each(|_| bearing = bearing.right()),
-                Action::Forward => {
-                    let (dx, dy) = bearing.vec();
-                    x += dx * amt;
-                    y += dy * amt;
-                }
-            }
-        }
-
-        x.abs() + y.abs()
+        Ferry::navigate(Mode::Heading, &input).distance()
     }
 
     fn part2(input: Self::Input) -> Self::Output2 {
-        let mut waypoint = (10, -1);
-        let mut x = 0;
-        let mut y = 0;
-        for instruction in input {
-            let amt = instruction.amount;
-            match instruction.action {
-                Action::North => waypoint.1 -= amt,
-                Action::South => waypoint.1 += amt,
-                Action::East => waypoint.0 += amt,
-                Action::West => waypoint.0 -= amt,
-                Action::Left => {
-                    for _ in 0..amt {
-                        waypoint = (waypoint.1, -waypoint.0);
-                    }
-                }
-                Action::Right => {
-                    for _ in 0..amt {
-                        waypoint = (-waypoint.1, waypoint.0);
-                    }
-                }
-                Action::Forward => {
-                    for _ in 0..amt {
-                        x += waypoint.0;
-                        y += waypoint.1;
-                    }
-                }
-            }
-        }
-
-        x.abs() + y.abs()
+        Ferry::navigate(Mode::Waypoint, &input).distance()
     }
 }
 
@@ -167,4 +230,32 @@ mod test {
     fn test_day12_part2() {
         assert_eq!(Day12::part2(sample_input()), 286);
     }
+
+    #[test]
+    fn test_day12_turns() {
+        assert!("L45".parse::<Instruction>().is_err());
+        assert!("R".parse::<Instruction>().is_err());
+        let turns = ["L270", "R450", "L-90"]
+            .iter()
+            .map(|s| s.parse().unwrap())
+            .collect::<Vec<_>>();
+        let ferry = Ferry::navigate(Mode::Heading, &turns);
+        assert_eq!(ferry.position().waypoint, (0, -1));
+    }
+
+    #[test]
+    fn test_day12_trajectory() {
+        let ferry = Ferry::navigate(Mode::Waypoint, &sample_input());
+        assert_eq!(ferry.trajectory().len(), 6);
+        assert_eq!(
+            ferry.trajectory()[4],
+            Snapshot {
+                ship: (170, -38),
+                waypoint: (4, 10)
+            }
+        );
+        let csv = ferry.to_csv();
+        assert_eq!(csv.lines().nth(6), Some("5,214,72,4,10"));
+        assert!(ferry.to_svg().starts_with("<svg"));
+    }
 }
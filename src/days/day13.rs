@@ -1,54 +1,57 @@
 use crate::prelude::*;
 
+use crate::number_theory::{crt, Congruence};
+
+fn parse_buses(s: &str) -> Result<Vec<Option<u64>>, Error> {
+    s.split(',')
+        .map(|b| match b.parse() {
+            Ok(0) => Err("bus ids must be positive".into()),
+            Ok(id) => Ok(Some(id)),
+            Err(_) => Ok(None),
+        })
+        .collect()
+}
+
 pub enum Day13 {}
 
 impl Challenge for Day13 {
     type Input = (u64, Vec<Option<u64>>);
-    type Output1 = u64;
-    type Output2 = u64;
+    type Output1 = u128;
+    type Output2 = u128;
 
     fn read(data: File) -> Result<Self::Input, Error> {
         let mut lines = data.lines();
         let start = lines.next().unwrap()?.parse()?;
-        let buses = lines
-            .next()
-            .unwrap()?
-            .split(',')
-            .map(|b| b.parse().ok())
-            .collect();
+        let buses = parse_buses(&lines.next().unwrap()?)?;
         Ok((start, buses))
     }
 
     fn part1((start, buses): Self::Input) -> Self::Output1 {
-        for t in start.. {
-            for id in buses.iter().copied().filter_map(|x| x) {
-                if t % id == 0 {
-                    return id * (t - start);
-                }
-            }
-        }
-        unreachable!()
+        let start = u128::from(start);
+        buses
+            .into_iter()
+            .flatten()
+            .map(|id| {
+                let bus = Congruence::new(0, id.into()).unwrap();
+                (bus.modulus, bus.next_from(start).unwrap() - start)
+            })
+            .min_by_key(|&(_, wait)| wait)
+            .map(|(id, wait)| id * wait)
+            .unwrap()
     }
 
     fn part2((_, buses): Self::Input) -> Self::Output2 {
-        let mut buses = buses
+        let congruences = buses
             .into_iter()
             .enumerate()
-            .filter_map(|(a, b)| Some((a as u64, b?)));
-
-        let mut step = buses.next().unwrap().1;
-        let mut t = 0;
-
-        for (dt, id) in buses {
-            while (t + dt) % id != 0 {
-                t += step;
-            }
-            step *= id;
-            // now all future values of t will satisfy this bus's condition
-            // so now we can worry only about the remaining buses
-        }
+            .filter_map(|(dt, id)| Some((dt as u128, u128::from(id?))))
+            // the bus leaves `dt` after the answer, so the answer is `-dt` modulo its id
+            .map(|(dt, id)| {
+                let offset = Congruence::new(dt, id).unwrap();
+                Congruence::new(id - offset.residue, id).unwrap()
+            });
 
-        t
+        crt(congruences).unwrap().residue
     }
 }
 
@@ -75,10 +78,16 @@ mod test {
     #[test]
     fn test_day13_part1() {
         assert_eq!(Day13::part1(sample_input()), 295);
+        assert!(parse_buses("7,x,0").is_err());
     }
 
     #[test]
     fn test_day13_part2() {
-        assert_eq!(Day13::part2(sample_input()), 1068781_u64.into());
+        assert_eq!(Day13::part2(sample_input()), 1068781);
+        assert_eq!(Day13::part2((0, parse_buses("17,x,13,19").unwrap())), 3417);
+        assert_eq!(
+            Day13::part2((0, parse_buses("1789,37,47,1889").unwrap())),
+            1202161486
+        );
     }
 }
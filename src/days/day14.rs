@@ -1,10 +1,123 @@
 use crate::prelude::*;
 
-use std::collections::HashMap;
+use std::{collections::HashMap, fmt, fmt::Write};
 
-#[derive(Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Mask {
+    width: u32,
+    ones: u64,
+    zeros: u64,
+    floating: u64,
+}
+
+impl Mask {
+    pub fn floating(width: u32) -> Self {
+        Self {
+            width,
+            ones: 0,
+            zeros: 0,
+            floating: u64::MAX >> (64 - width),
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn apply(&self, val: u64) -> u64 {
+        (val | self.ones) & !self.zeros
+    }
+}
+
+impl Default for Mask {
+    fn default() -> Self {
+        Self::floating(36)
+    }
+}
+
+impl FromStr for Mask {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let width = s.len() as u32;
+        if !(1..=64).contains(&width) {
+            return Err("mask must be between 1 and 64 bits wide".into());
+        }
+
+        let mut mask = Self {
+            width,
+            ones: 0,
+            zeros: 0,
+            floating: 0,
+        };
+        for (i, c) in s.chars().rev().enumerate() {
+            let b = 1 << i;
+            match c {
+                '0' => mask.zeros |= b,
+                '1' => mask.ones |= b,
+                'X' => mask.floating |= b,
+                _ => Err("bad mask bit")?,
+            }
+        }
+        Ok(mask)
+    }
+}
+
+impl fmt::Display for Mask {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for i in (0..self.width).rev() {
+            let b = 1 << i;
+            let c = if self.ones & b != 0 {
+                '1'
+            } else if self.zeros & b != 0 {
+                '0'
+            } else {
+                'X'
+            };
+            write!(f, "{}", c)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Token<'a> {
+    Word(&'a str),
+    LBracket,
+    RBracket,
+    Equals,
+}
+
+fn tokenize(s: &str) -> Result<Vec<Token<'_>>, Error> {
+    let mut tokens = Vec::new();
+    let mut chars = s.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        let token = match c {
+            '[' => Token::LBracket,
+            ']' => Token::RBracket,
+            '=' => Token::Equals,
+            c if c.is_whitespace() => continue,
+            c if c.is_ascii_alphanumeric() => {
+                let mut end = i + 1;
+                while let Some(&(j, c)) = chars.peek() {
+                    if !c.is_ascii_alphanumeric() {
+                        break;
+                    }
+                    end = j + 1;
+                    chars.next();
+                }
+                Token::Word(&s[i..end])
+            }
+            _ => Err("unexpected character")?,
+        };
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum Instruction {
-    Mask([Option<bool>; 36]),
+    Mask(Mask),
     Write(u64, u64),
 }
 
@@ -12,109 +125,245 @@ impl FromStr for Instruction {
     type Err = Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match &s[..3] {
-            "mas" => {
-                let mut mask = [None; 36];
-                for (i, c) in s[7..].chars().enumerate() {
-                    mask[i] = match c {
-                        '0' => Some(false),
-                        '1' => Some(true),
-                        'X' => None,
-                        _ => Err("bad mask bit")?,
-                    };
-                }
-                Ok(Self::Mask(mask))
-            }
-            "mem" => {
-                let bracket = s.find(']').ok_or("bad write syntax")?;
-                let addr = s[4..bracket].parse()?;
-                let val = s[bracket + 4..].parse()?;
-                Ok(Self::Write(addr, val))
+        use Token::*;
+        match tokenize(s)?.as_slice() {
+            [Word("mask"), Equals, Word(bits)] => Ok(Self::Mask(bits.parse()?)),
+            [Word("mem"), LBracket, Word(addr), RBracket, Equals, Word(val)] => {
+                Ok(Self::Write(addr.parse()?, val.parse()?))
             }
             _ => Err("bad instr")?,
         }
     }
 }
 
-fn apply_mask(mut val: u64, mask: &[Option<bool>; 36]) -> u64 {
-    for i in 0..36 {
-        if let Some(bit) = mask[35 - i] {
-            let b = 1 << i;
-            if bit {
-                val |= b;
-            } else {
-                val &= !b;
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Mask(mask) => write!(f, "mask = {}", mask),
+            Self::Write(addr, val) => write!(f, "mem[{}] = {}", addr, val),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct AddressSet {
+    fixed: u64,
+    floating: u64,
+}
+
+impl AddressSet {
+    pub fn new(addr: u64, mask: &Mask) -> Self {
+        Self {
+            fixed: (addr | mask.ones) & !mask.floating,
+            floating: mask.floating,
+        }
+    }
+
+    pub fn pattern(&self, width: u32) -> String {
+        (0..width)
+            .rev()
+            .map(|i| match (self.floating >> i & 1, self.fixed >> i & 1) {
+                (1, _) => 'X',
+                (_, 1) => '1',
+                _ => '0',
+            })
+            .collect()
+    }
+
+    pub fn size(&self) -> u128 {
+        1 << self.floating.count_ones()
+    }
+
+    pub fn contains(&self, addr: u64) -> bool {
+        addr & !self.floating == self.fixed
+    }
+
+    pub fn intersection(&self, other: &Self) -> Option<Self> {
+        let fixed_in_both = !self.floating & !other.floating;
+        if (self.fixed ^ other.fixed) & fixed_in_both != 0 {
+            return None;
+        }
+        Some(Self {
+            fixed: self.fixed | other.fixed,
+            floating: self.floating & other.floating,
+        })
+    }
+}
+
+/// Memory where each write covers a whole `AddressSet`. Writes are kept in order, minus any that
+/// a later write completely covers; an address holds the value of the last write that covers it.
+#[derive(Debug, Clone, Default)]
+pub struct FloatingMemory {
+    writes: Vec<(AddressSet, u64)>,
+}
+
+impl FloatingMemory {
+    pub fn write(&mut self, addrs: AddressSet, val: u64) {
+        self.writes
+            .retain(|(set, _)| set.intersection(&addrs) != Some(*set));
+        self.writes.push((addrs, val));
+    }
+
+    pub fn read(&self, addr: u64) -> u64 {
+        self.writes
+            .iter()
+            .rev()
+            .find(|(set, _)| set.contains(addr))
+            .map_or(0, |&(_, val)| val)
+    }
+
+    /// Goes through the writes from last to first, counting only the addresses no later write
+    /// has covered. The covered region is kept by inclusion-exclusion as signed weights on
+    /// address sets, and writes that are already hidden are skipped without touching it.
+    pub fn sum(&self) -> Result<u128, Error> {
+        let overflow = || Error::from("memory sum overflowed");
+        let mut covered: HashMap<AddressSet, i128> = HashMap::new();
+        let mut total: u128 = 0;
+        for &(addrs, val) in self.writes.iter().rev() {
+            let overlaps = covered
+                .iter()
+                .filter_map(|(set, &weight)| Some((set.intersection(&addrs)?, weight)))
+                .collect::<Vec<_>>();
+            let mut hidden: i128 = 0;
+            for (set, weight) in &overlaps {
+                hidden = weight
+                    .checked_mul(set.size() as i128)
+                    .and_then(|n| hidden.checked_add(n))
+                    .ok_or_else(overflow)?;
+            }
+            let visible = (addrs.size() as i128)
+                .checked_sub(hidden)
+                .ok_or_else(overflow)?;
+            if visible == 0 {
+                continue;
             }
+
+            total = u128::from(val)
+                .checked_mul(visible as u128)
+                .and_then(|n| total.checked_add(n))
+                .ok_or_else(overflow)?;
+            for (set, weight) in overlaps {
+                *covered.entry(set).or_default() -= weight;
+            }
+            *covered.entry(addrs).or_default() += 1;
+            covered.retain(|_, weight| *weight != 0);
         }
+        Ok(total)
+    }
+
+    pub fn writes(&self) -> impl Iterator<Item = (&AddressSet, u64)> {
+        self.writes.iter().map(|(set, val)| (set, *val))
     }
-    val
 }
 
-fn what(mut addr: u64, mask: &[Option<bool>; 36]) -> Vec<u64> {
-    let mut floating_bits = Vec::new();
-    for i in 0..36 {
-        let b = 1 << i;
-        match mask[35 - i] {
-            Some(true) => addr |= b,
-            Some(false) => (),
-            None => floating_bits.push(b),
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Version {
+    /// Masks apply to values.
+    V1,
+    /// Masks apply to addresses, with floating bits.
+    V2,
+}
+
+pub struct Interpreter {
+    version: Version,
+    mask: Mask,
+    width: u32,
+    values: HashMap<u64, u64>,
+    floating: FloatingMemory,
+}
+
+impl Interpreter {
+    pub fn new(version: Version) -> Self {
+        let mask = Mask::default();
+        Self {
+            version,
+            mask,
+            width: mask.width,
+            values: HashMap::new(),
+            floating: FloatingMemory::default(),
+        }
+    }
+
+    pub fn execute(&mut self, instr: &Instruction) {
+        match (*instr, self.version) {
+            (Instruction::Mask(mask), _) => {
+                self.mask = mask;
+                self.width = self.width.max(mask.width);
+            }
+            (Instruction::Write(addr, val), Version::V1) => {
+                self.values.insert(addr, self.mask.apply(val));
+            }
+            (Instruction::Write(addr, val), Version::V2) => {
+                self.floating.write(AddressSet::new(addr, &self.mask), val);
+            }
+        }
+    }
+
+    pub fn run(&mut self, program: &[Instruction]) {
+        program.iter().for_each(|instr| self.execute(instr));
+    }
+
+    pub fn run_traced(
+        &mut self,
+        program: &[Instruction],
+        out: &mut impl std::io::Write,
+    ) -> std::io::Result<()> {
+        for instr in program {
+            self.execute(instr);
+            writeln!(out, "> {}", instr)?;
+            out.write_all(self.dump().as_bytes())?;
         }
+        Ok(())
     }
-    let mut addrs = vec![addr];
-    for b in floating_bits {
-        for a in &mut addrs {
-            *a |= b;
+
+    pub fn sum(&self) -> Result<u128, Error> {
+        match self.version {
+            Version::V1 => Ok(self.values.values().map(|&v| u128::from(v)).sum()),
+            Version::V2 => self.floating.sum(),
         }
-        let mut zeroes = addrs.clone();
-        for a in &mut zeroes {
-            *a &= !b;
+    }
+
+    /// One line per memory entry. V1 entries are sorted by address; V2 entries are the stored
+    /// writes as address patterns, oldest first, with later lines overriding earlier ones.
+    pub fn dump(&self) -> String {
+        let mut out = String::new();
+        match self.version {
+            Version::V1 => {
+                for (addr, val) in self.values.iter().sorted() {
+                    writeln!(out, "mem[{}] = {}", addr, val).unwrap();
+                }
+            }
+            Version::V2 => {
+                for (set, val) in self.floating.writes() {
+                    writeln!(out, "mem[{}] = {}", set.pattern(self.width), val).unwrap();
+                }
+            }
         }
-        addrs.extend(zeroes);
+        out
     }
-    addrs
 }
 
 pub enum Day14 {}
 
 impl Challenge for Day14 {
     type Input = Vec<Instruction>;
-    type Output1 = u64;
-    type Output2 = u64;
+    type Output1 = u128;
+    type Output2 = u128;
 
     fn read(data: File) -> Result<Self::Input, Error> {
         data.parse_lines()
     }
 
     fn part1(input: Self::Input) -> Self::Output1 {
-        let mut memory = HashMap::new();
-        let mut mask = [None; 36];
-        for instr in input {
-            match instr {
-                Instruction::Mask(m) => mask = m,
-                Instruction::Write(addr, val) => {
-                    let x = memory.entry(addr).or_default();
-                    *x = apply_mask(val, &mask);
-                }
-            }
-        }
-        memory.values().sum()
+        let mut interpreter = Interpreter::new(Version::V1);
+        interpreter.run(&input);
+        interpreter.sum().unwrap()
     }
 
     fn part2(input: Self::Input) -> Self::Output2 {
-        let mut memory = HashMap::new();
-        let mut mask = [None; 36];
-        for instr in input {
-            match instr {
-                Instruction::Mask(m) => mask = m,
-                Instruction::Write(addr, val) => {
-                    for a in what(addr, &mask) {
-                        let x = memory.entry(a).or_default();
-                        *x = val;
-                    }
-                }
-            }
-        }
-        memory.values().sum()
+        let mut interpreter = Interpreter::new(Version::V2);
+        interpreter.run(&input);
+        interpreter.sum().unwrap()
     }
 }
 
@@ -157,4 +406,156 @@ mod test {
     fn test_day14_part2() {
         assert_eq!(Day14::part2(sample_input2()), 208);
     }
+
+    #[test]
+    fn test_day14_many_floating_bits() {
+        let input = [
+            "mask = XXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXX0",
+            "mem[0] = 3",
+            "mask = XXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXX1X",
+            "mem[0] = 5",
+            "mask = 0000XXXXXXXXXXXXXXXXXXXXXXXXXXXXXX11",
+            "mem[0] = 1",
+        ]
+        .iter()
+        .map(|s| s.parse().unwrap())
+        .collect();
+        let half = 1 << 34;
+        let quarter = 1 << 30;
+        assert_eq!(
+            Day14::part2(input),
+            3 * half + 5 * (2 * half - quarter) + quarter
+        );
+    }
+
+    #[test]
+    fn test_day14_floating_reads() {
+        let mut memory = FloatingMemory::default();
+        memory.write(AddressSet::new(0, &"00XX".parse().unwrap()), 7);
+        memory.write(AddressSet::new(1, &"0000".parse().unwrap()), 2);
+        assert_eq!(memory.read(0), 7);
+        assert_eq!(memory.read(1), 2);
+        assert_eq!(memory.read(3), 7);
+        assert_eq!(memory.read(4), 0);
+        assert_eq!(memory.sum().unwrap(), 7 * 3 + 2);
+
+        memory.write(AddressSet::new(0, &"000X".parse().unwrap()), 0);
+        assert_eq!(memory.read(0), 0);
+        assert_eq!(memory.read(2), 7);
+        assert_eq!(memory.sum().unwrap(), 7 * 2);
+        assert_eq!(memory.writes().count(), 2);
+    }
+
+    #[test]
+    fn test_day14_overlapping_writes() {
+        let mut state = 0x9e37_79b9_u64;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+
+        // 10 of the 16 bits float in every mask, in random places, so the writes overlap heavily
+        let mut memory = FloatingMemory::default();
+        let mut expected = vec![0; 1 << 16];
+        for val in 1..=200 {
+            let mut bits = [['X'; 10].as_ref(), &['0'; 3], &['1'; 3]].concat();
+            for i in (1..bits.len()).rev() {
+                bits.swap(i, next() as usize % (i + 1));
+            }
+            let mask = bits.iter().collect::<String>().parse().unwrap();
+            let addrs = AddressSet::new(next() & 0xffff, &mask);
+            memory.write(addrs, val);
+
+            let mut sub = 0;
+            loop {
+                expected[(addrs.fixed | sub) as usize] = val;
+                sub = sub.wrapping_sub(addrs.floating) & addrs.floating;
+                if sub == 0 {
+                    break;
+                }
+            }
+        }
+
+        for addr in (0..1 << 16).step_by(97) {
+            assert_eq!(memory.read(addr), expected[addr as usize]);
+        }
+        assert_eq!(
+            memory.sum().unwrap(),
+            expected.into_iter().map(u128::from).sum()
+        );
+    }
+
+    #[test]
+    fn test_day14_parsing() {
+        let mask = "mask=X1".parse::<Instruction>().unwrap();
+        assert_eq!(mask, Instruction::Mask("X1".parse().unwrap()));
+        assert_eq!(mask.to_string(), "mask = X1");
+        let write = "  mem [ 8 ]=11 ".parse::<Instruction>().unwrap();
+        assert_eq!(write, Instruction::Write(8, 11));
+        assert!("mem[8] = ".parse::<Instruction>().is_err());
+        assert!("mask = 01Y".parse::<Instruction>().is_err());
+        assert!("mask = ".parse::<Instruction>().is_err());
+        assert!(format!("mask = {}", "X".repeat(65))
+            .parse::<Instruction>()
+            .is_err());
+    }
+
+    #[test]
+    fn test_day14_wide_words() {
+        let program = [
+            format!("mask = 1{}", "X".repeat(63)),
+            "mem[3] = 5".to_string(),
+        ];
+        let program = program
+            .iter()
+            .map(|s| s.parse().unwrap())
+            .collect::<Vec<_>>();
+        let mut interpreter = Interpreter::new(Version::V1);
+        interpreter.run(&program);
+        assert_eq!(interpreter.sum().unwrap(), (1 << 63) + 5);
+        assert_eq!(
+            interpreter.dump(),
+            format!("mem[3] = {}\n", (1_u64 << 63) + 5)
+        );
+    }
+
+    #[test]
+    fn test_day14_full_width_floating() {
+        let program = [
+            format!("mask = {}", "X".repeat(64)),
+            format!("mem[0] = {}", u64::MAX),
+        ];
+        let mut program = program
+            .iter()
+            .map(|s| s.parse().unwrap())
+            .collect::<Vec<_>>();
+        let mut interpreter = Interpreter::new(Version::V2);
+        interpreter.run(&program);
+        let everywhere = u128::from(u64::MAX) << 64;
+        assert_eq!(interpreter.sum().unwrap(), everywhere);
+
+        // clearing the top half of memory leaves the bottom half
+        program.push(format!("mask = 1{}", "X".repeat(63)).parse().unwrap());
+        program.push("mem[0] = 0".parse().unwrap());
+        let mut interpreter = Interpreter::new(Version::V2);
+        interpreter.run(&program);
+        assert_eq!(interpreter.sum().unwrap(), everywhere / 2);
+    }
+
+    #[test]
+    fn test_day14_trace() {
+        let mut interpreter = Interpreter::new(Version::V2);
+        let mut out = Vec::new();
+        interpreter
+            .run_traced(&sample_input2()[..2], &mut out)
+            .unwrap();
+        let expected = [
+            "> mask = 000000000000000000000000000000X1001X",
+            "> mem[42] = 100",
+            "mem[000000000000000000000000000000X1101X] = 100",
+        ];
+        assert_eq!(String::from_utf8(out).unwrap(), expected.join("\n") + "\n");
+    }
 }
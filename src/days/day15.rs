@@ -1,28 +1,97 @@
 use crate::prelude::*;
 
 use std::collections::HashMap;
+use std::convert::TryFrom;
 
-pub enum Day15 {}
+/// Numbers below this are kept in the flat array unless the game is known to be shorter.
+const DEFAULT_DENSE_LIMIT: usize = 1 << 26;
+
+/// Yields the spoken numbers. Numbers below `dense_limit` have their last-spoken turn kept in a
+/// flat array; larger ones are rare, so they go in a hash map. Turns are stored as `u32`, so the
+/// game ends once it runs past `u32::MAX` turns rather than recording a truncated turn.
+pub struct MemoryGame {
+    starting: Vec<usize>,
+    turn: usize,
+    next: usize,
+    dense: Vec<u32>,
+    sparse: HashMap<usize, usize>,
+    dense_limit: usize,
+}
+
+impl MemoryGame {
+    pub fn new(starting: &[u64]) -> Self {
+        Self::with_dense_limit(starting, DEFAULT_DENSE_LIMIT)
+    }
+
+    pub fn with_dense_limit(starting: &[u64], dense_limit: usize) -> Self {
+        Self {
+            starting: starting.iter().map(|&n| n as usize).collect(),
+            turn: 0,
+            next: 0,
+            dense: Vec::new(),
+            sparse: HashMap::new(),
+            dense_limit,
+        }
+    }
 
-fn play_until(end: u64, input: &[u64]) -> u64 {
-    let mut t = 0;
-    let mut last_spoken = 0;
-    let mut foo = HashMap::new();
-    for n in input.iter().copied() {
-        foo.insert(n, t);
-        last_spoken = n;
-        t += 1;
+    /// A game that will be played for at most `turns` turns. No number spoken after the starting
+    /// ones can reach `turns`, so the array is sized to that up front instead of growing.
+    pub fn with_turns(starting: &[u64], turns: usize) -> Self {
+        let dense_limit = turns.min(DEFAULT_DENSE_LIMIT);
+        let mut game = Self::with_dense_limit(starting, dense_limit);
+        game.dense = vec![0; dense_limit];
+        game
     }
-    while t < end {
-        let bar = foo.entry(last_spoken).or_insert(t - 1);
-        let age = (t - 1) - *bar;
-        *bar = t - 1;
-        last_spoken = age;
-        t += 1;
+
+    /// Records `n` as spoken on `turn` and returns the turn it was previously spoken on, or `None`
+    /// in the outer option if `turn` doesn't fit in the array.
+    fn speak(&mut self, n: usize, turn: usize) -> Option<Option<usize>> {
+        if n < self.dense_limit {
+            let stamp = u32::try_from(turn).ok()?.checked_add(1)?;
+            if n >= self.dense.len() {
+                let len = (n + 1).max(self.dense.len() * 2).min(self.dense_limit);
+                self.dense.resize(len, 0);
+            }
+            let prev = std::mem::replace(&mut self.dense[n], stamp);
+            Some(prev.checked_sub(1).map(|t| t as usize))
+        } else {
+            Some(self.sparse.insert(n, turn))
+        }
+    }
+
+    /// The turn (counting from 1) on which `n` is first spoken, if it happens within `limit` turns.
+    pub fn first_spoken(starting: &[u64], n: u64, limit: usize) -> Option<usize> {
+        Self::with_turns(starting, limit)
+            .take(limit)
+            .position(|x| x == n)
+            .map(|i| i + 1)
+    }
+}
+
+impl Iterator for MemoryGame {
+    type Item = u64;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let turn = self.turn;
+        let spoken = self.starting.get(turn).copied().unwrap_or(self.next);
+        self.next = self.speak(spoken, turn)?.map_or(0, |prev| turn - prev);
+        self.turn += 1;
+        Some(spoken as u64)
     }
-    last_spoken
 }
 
+/// The number spoken on turn `end`, counting from 1.
+pub fn play_until(end: usize, input: &[u64]) -> Result<u64, Error> {
+    if end == 0 {
+        return Err("turns are counted from 1".into());
+    }
+    MemoryGame::with_turns(input, end)
+        .nth(end - 1)
+        .ok_or_else(|| "game ran past the last recordable turn".into())
+}
+
+pub enum Day15 {}
+
 impl Challenge for Day15 {
     type Input = Vec<u64>;
     type Output1 = u64;
@@ -35,11 +104,11 @@ impl Challenge for Day15 {
     }
 
     fn part1(input: Self::Input) -> Self::Output1 {
-        play_until(2020, &input)
+        play_until(2020, &input).unwrap()
     }
 
     fn part2(input: Self::Input) -> Self::Output2 {
-        play_until(30000000, &input)
+        play_until(30000000, &input).unwrap()
     }
 }
 
@@ -67,7 +136,6 @@ mod test {
         );
     }
 
-    /*
     #[test]
     fn test_day15_part2() {
         assert_eq!(
@@ -75,5 +143,32 @@ mod test {
             [175594, 2578, 3544142, 261214, 6895259, 18, 362],
         );
     }
-    */
+
+    #[test]
+    fn test_day15_sequence() {
+        let game = MemoryGame::new(&[0, 3, 6]);
+        assert_eq!(game.take(10).collect_vec(), [0, 3, 6, 0, 3, 3, 1, 0, 4, 0]);
+        assert_eq!(MemoryGame::first_spoken(&[0, 3, 6], 4, 100), Some(9));
+        assert_eq!(MemoryGame::first_spoken(&[0, 3, 6], 2, 8), None);
+
+        let sparse = MemoryGame::with_dense_limit(&[0, 3, 6], 2);
+        assert_eq!(sparse.take(2020).last(), Some(436));
+
+        assert!(play_until(0, &[0, 3, 6]).is_err());
+        assert_eq!(play_until(1, &[0, 3, 6]).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_day15_dense_limit() {
+        // numbers spoken in the first few thousand turns cross each of these limits, so every
+        // game switches between the array and the map partway through
+        for starting in sample_input() {
+            let expected = MemoryGame::new(&starting).take(5000).collect_vec();
+            assert!(expected.iter().any(|&n| n >= 1000));
+            for &limit in &[0, 1, 4, 50, 1000] {
+                let game = MemoryGame::with_dense_limit(&starting, limit);
+                assert_eq!(game.take(5000).collect_vec(), expected);
+            }
+        }
+    }
 }
@@ -1,17 +1,31 @@
-use crate::prelude::*;
-
-use std::{
-    collections::{HashMap, HashSet},
-    ops::RangeInclusive,
+use crate::{
+    assignment::{self, Candidates},
+    prelude::*,
 };
 
-#[derive(Debug, Clone)]
-struct Range(RangeInclusive<usize>, RangeInclusive<usize>);
+use std::{collections::HashMap, ops::RangeInclusive};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rule(Vec<RangeInclusive<usize>>);
+
+impl Rule {
+    fn contains(&self, n: usize) -> bool {
+        self.0.iter().any(|range| range.contains(&n))
+    }
+}
+
+impl FromStr for Rule {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.split(" or ").map(parse_range).try_collect().map(Self)
+    }
+}
 
 fn parse_range(s: &str) -> Result<RangeInclusive<usize>, Error> {
     let dash = s.find('-').ok_or("no dash")?;
-    let a = s[..dash].parse()?;
-    let b = s[(dash + 1)..].parse()?;
+    let a = s[..dash].trim().parse()?;
+    let b = s[(dash + 1)..].trim().parse()?;
     Ok(a..=b)
 }
 
@@ -22,27 +36,24 @@ fn parse_ticket(s: &str) -> Result<Ticket, Error> {
 type Ticket = Vec<usize>;
 
 pub struct Input {
-    fields: HashMap<String, Range>,
+    fields: HashMap<String, Rule>,
     my_ticket: Ticket,
     tickets: Vec<Ticket>,
 }
 
-impl Range {
-    fn contains(&self, n: usize) -> bool {
-        self.0.contains(&n) || self.1.contains(&n)
-    }
-}
-
-fn invalid(val: usize, fields: &HashMap<String, Range>) -> bool {
-    !fields.values().any(|range| range.contains(val))
+fn invalid(val: usize, fields: &HashMap<String, Rule>) -> bool {
+    !fields.values().any(|rule| rule.contains(val))
 }
 
-fn determine_fields(fields: &HashMap<String, Range>, tickets: &[Ticket]) -> HashMap<String, usize> {
+fn field_candidates(
+    fields: &HashMap<String, Rule>,
+    tickets: &[Ticket],
+) -> Candidates<String, usize> {
     let n_fields = fields.len();
 
-    let mut mappings: HashMap<&String, HashSet<usize>> = fields
+    let mut candidates: Candidates<String, usize> = fields
         .keys()
-        .map(|k| (k, (0..n_fields).collect()))
+        .map(|k| (k.clone(), (0..n_fields).collect()))
         .collect();
 
     for ticket in tickets {
@@ -52,38 +63,39 @@ fn determine_fields(fields: &HashMap<String, Range>, tickets: &[Ticket]) -> Hash
         }
 
         for (i, val) in ticket.iter().enumerate() {
-            for (name, map) in &mut mappings {
-                if !fields[*name].contains(*val) {
-                    map.remove(&i);
+            for (name, positions) in &mut candidates {
+                if !fields[name].contains(*val) {
+                    positions.remove(&i);
                 }
             }
         }
     }
 
-    loop {
-        let mut changed = false;
-
-        for name in fields.keys() {
-            if let Ok(&i) = mappings[name].iter().exactly_one() {
-                for (n, map) in &mut mappings {
-                    if n != &name {
-                        changed |= map.remove(&i);
-                    }
-                }
-            }
-        }
-
-        if !changed {
-            break;
-        }
-    }
+    candidates
+}
 
-    assert!(mappings.values().all(|m| m.len() == 1));
+/// Every way of assigning fields to distinct positions that is consistent with the valid tickets.
+pub fn determine_fields(
+    fields: &HashMap<String, Rule>,
+    tickets: &[Ticket],
+) -> Vec<HashMap<String, usize>> {
+    assignment::all(&field_candidates(fields, tickets))
+}
 
-    mappings
-        .into_iter()
-        .map(|(k, v)| (k.clone(), v.into_iter().exactly_one().unwrap()))
-        .collect()
+pub fn field_product(input: &Input, prefix: &str) -> Result<usize, Error> {
+    let assignment = assignment::unique(&field_candidates(&input.fields, &input.tickets))?;
+
+    assignment
+        .iter()
+        .filter(|(k, _)| k.starts_with(prefix))
+        .map(|(_, &v)| {
+            input
+                .my_ticket
+                .get(v)
+                .copied()
+                .ok_or_else(|| "my ticket is missing a field".into())
+        })
+        .product()
 }
 
 pub enum Day16 {}
@@ -99,18 +111,12 @@ impl Challenge for Day16 {
         let mut fields = HashMap::new();
         for line in lines.by_ref() {
             let line = line?;
-            if line == "" {
+            if line.is_empty() {
                 break;
             }
             let colon = line.find(':').ok_or("no colon")?;
             let field = line[..colon].into();
-            let (r1, _, r2) = line[(colon + 2)..]
-                .split(' ')
-                .collect_tuple()
-                .ok_or("bad rule fmt")?;
-            let r1 = parse_range(r1)?;
-            let r2 = parse_range(r2)?;
-            fields.insert(field, Range(r1, r2));
+            fields.insert(field, line[(colon + 1)..].trim().parse()?);
         }
 
         lines.next(); // "your ticket:"
@@ -141,17 +147,7 @@ impl Challenge for Day16 {
     }
 
     fn part2(input: Self::Input) -> Self::Output2 {
-        let Input {
-            fields,
-            my_ticket,
-            tickets,
-        } = input;
-
-        determine_fields(&fields, &tickets)
-            .into_iter()
-            .filter(|(k, _)| k.starts_with("departure"))
-            .map(|(_, v)| my_ticket[v])
-            .product()
+        field_product(&input, "departure").unwrap()
     }
 }
 
@@ -161,9 +157,9 @@ mod test {
 
     fn sample_input() -> <Day16 as Challenge>::Input {
         let mut fields = HashMap::new();
-        fields.insert("class".into(), Range(1..=3, 5..=7));
-        fields.insert("row".into(), Range(6..=11, 33..=44));
-        fields.insert("seat".into(), Range(13..=40, 45..=50));
+        fields.insert("class".into(), "1-3 or 5-7".parse().unwrap());
+        fields.insert("row".into(), "6-11 or 33-44".parse().unwrap());
+        fields.insert("seat".into(), "13-40 or 45-50".parse().unwrap());
 
         Input {
             fields,
@@ -191,6 +187,40 @@ mod test {
             .collect();
 
         let input = sample_input();
-        assert_eq!(determine_fields(&input.fields, &input.tickets), expected);
+        assert_eq!(
+            determine_fields(&input.fields, &input.tickets),
+            vec![expected]
+        );
+        assert_eq!(field_product(&input, "s").unwrap(), 14);
+        assert_eq!(field_product(&input, "").unwrap(), 7 * 14);
+
+        let short = Input {
+            my_ticket: vec![7, 1],
+            ..input
+        };
+        assert!(field_product(&short, "s").is_err());
+        assert_eq!(field_product(&short, "c").unwrap(), 1);
+    }
+
+    #[test]
+    fn test_day16_ambiguous() {
+        let mut fields = HashMap::new();
+        fields.insert("a".to_string(), "1-2 or 4-5 or 7-7".parse().unwrap());
+        fields.insert("b".to_string(), "1-5".parse().unwrap());
+        fields.insert("c".to_string(), "9-9 or 20-30".parse().unwrap());
+        let input = Input {
+            fields,
+            my_ticket: vec![1, 2, 3],
+            tickets: vec![vec![1, 2, 9], vec![4, 5, 9]],
+        };
+
+        // c must take position 2, but a and b can swap
+        let assignments = determine_fields(&input.fields, &input.tickets);
+        assert_eq!(assignments.len(), 2);
+        assert!(assignments.iter().all(|a| a["c"] == 2));
+        assert!(field_product(&input, "c").is_err());
+
+        let tickets = vec![vec![1, 9, 9]];
+        assert!(determine_fields(&input.fields, &tickets).is_empty());
     }
 }
@@ -1,61 +1,118 @@
 use crate::prelude::*;
 
-use itertools::MinMaxResult;
-use std::{collections::HashSet, convert::TryInto, ops::RangeInclusive};
+use std::collections::{HashMap, HashSet};
+
+type Point = Vec<i32>;
+
+/// A sparse Conway cube simulation in any number of dimensions.
+///
+/// The starting slice lies where every axis past the first two is zero, so the state stays
+/// symmetric under negating or permuting those extra axes. With folding enabled, only one
+/// canonical point (extra coordinates non-negative and sorted) is stored per symmetry class.
+pub struct Pocket {
+    dims: usize,
+    fold: bool,
+    offsets: Vec<Point>,
+    active: HashSet<Point>,
+}
 
-type Point<const N: usize> = [i8; N];
-type Volume<const N: usize> = [RangeInclusive<i8>; N];
-type State<const N: usize> = HashSet<Point<N>>;
+impl Pocket {
+    pub fn new(input: &[Vec<bool>], dims: usize) -> Self {
+        assert!(dims >= 2, "the starting slice needs two dimensions");
+
+        let mut active = HashSet::new();
+        for (y, row) in input.iter().enumerate() {
+            for (x, val) in row.iter().enumerate() {
+                if *val {
+                    let mut point = vec![0; dims];
+                    point[0] = x as i32;
+                    point[1] = y as i32;
+                    active.insert(point);
+                }
+            }
+        }
 
-fn neighborhood<const N: usize>(point: &Point<N>) -> Volume<N> {
-    point.map(|a| (a - 1)..=(a + 1))
-}
+        let offsets = (0..dims)
+            .map(|_| -1..=1)
+            .multi_cartesian_product()
+            .filter(|p: &Point| p.iter().any(|&d| d != 0))
+            .collect();
+
+        Self {
+            dims,
+            fold: true,
+            offsets,
+            active,
+        }
+    }
 
-fn iter_vol<const N: usize>(vol: Volume<N>) -> impl Iterator<Item = Point<N>> {
-    vol.iter()
-        .cloned()
-        .multi_cartesian_product()
-        .map(|p| p.try_into().unwrap())
-}
+    pub fn with_folding(mut self, fold: bool) -> Self {
+        self.fold = fold;
+        self
+    }
 
-fn next<const N: usize>(prev: &State<N>) -> State<N> {
-    let minmax = |i| prev.iter().map(|p| p[i]).minmax();
-    let mut vol = [0; N].map(|_| 0..=0); // stupid non-Copy ranges and non-Default arrays
-    for i in 0..N {
-        vol[i] = match minmax(i) {
-            MinMaxResult::MinMax(min, max) => (min - 1)..=(max + 1),
-            // if there aren't at least 3 live cells, next gen has no life
-            _ => return HashSet::new(),
-        };
-    }
-
-    let mut new = HashSet::new();
-    for cell in iter_vol(vol) {
-        let neighbor_count = iter_vol(neighborhood(&cell))
-            .filter(|p| p != &cell)
-            .filter(|p| prev.contains(p))
-            .count();
-
-        if neighbor_count == 3 || (neighbor_count == 2 && prev.contains(&cell)) {
-            new.insert(cell);
+    fn canonicalize(&self, mut point: Point) -> Point {
+        if self.fold {
+            let extra = &mut point[2..];
+            extra.iter_mut().for_each(|a| *a = a.abs());
+            extra.sort_unstable();
         }
+        point
+    }
+
+    /// The number of actual points represented by a canonical point.
+    fn multiplicity(&self, point: &[i32]) -> u128 {
+        if !self.fold {
+            return 1;
+        }
+
+        let extra = &point[2..];
+        let factorial = |n: usize| (1..=n as u128).product::<u128>();
+        let arrangements = extra
+            .iter()
+            .group_by(|&&a| a)
+            .into_iter()
+            .fold(factorial(extra.len()), |acc, (_, run)| {
+                acc / factorial(run.count())
+            });
+        let signs = extra.iter().filter(|&&a| a != 0).count();
+        arrangements << signs
     }
-    new
-}
 
-fn parse<const N: usize>(input: &[Vec<bool>]) -> State<N> {
-    let mut state = HashSet::new();
-    for (y, row) in input.iter().enumerate() {
-        for (x, val) in row.iter().enumerate() {
-            if *val {
-                let mut point = [0; N];
-                point[0] = x as _;
-                point[1] = y as _;
-                state.insert(point);
+    pub fn step(&mut self) {
+        // Weighting each contribution by the source's class size and dividing by the target's
+        // afterwards gives the neighbour count seen by any single point of the target class.
+        let mut counts: HashMap<Point, u128> = HashMap::new();
+        for cell in &self.active {
+            let weight = self.multiplicity(cell);
+            for offset in &self.offsets {
+                let neighbor = cell.iter().zip(offset).map(|(a, d)| a + d).collect();
+                *counts.entry(self.canonicalize(neighbor)).or_default() += weight;
             }
         }
+
+        self.active = counts
+            .into_iter()
+            .filter(|(cell, count)| {
+                let count = count / self.multiplicity(cell);
+                count == 3 || (count == 2 && self.active.contains(cell))
+            })
+            .map(|(cell, _)| cell)
+            .collect();
+    }
+
+    pub fn run(&mut self, cycles: usize) -> &mut Self {
+        (0..cycles).for_each(|_| self.step());
+        self
+    }
+
+    pub fn dims(&self) -> usize {
+        self.dims
+    }
+
+    pub fn active(&self) -> u128 {
+        self.active.iter().map(|p| self.multiplicity(p)).sum()
     }
-    state
 }
 
 pub enum Day17 {}
@@ -73,19 +130,11 @@ impl Challenge for Day17 {
     }
 
     fn part1(input: Self::Input) -> Self::Output1 {
-        let mut state: State<3> = parse(&input);
-        for _ in 0..6 {
-            state = next(&state);
-        }
-        state.len()
+        Pocket::new(&input, 3).run(6).active() as usize
     }
 
     fn part2(input: Self::Input) -> Self::Output2 {
-        let mut state: State<4> = parse(&input);
-        for _ in 0..6 {
-            state = next(&state);
-        }
-        state.len()
+        Pocket::new(&input, 4).run(6).active() as usize
     }
 }
 
@@ -110,4 +159,28 @@ mod test {
     fn test_day17_part2() {
         assert_eq!(Day17::part2(sample_input()), 848);
     }
+
+    #[test]
+    fn test_day17_folding() {
+        for dims in 3..=5 {
+            let folded = Pocket::new(&sample_input(), dims).run(4).active();
+            let unfolded = Pocket::new(&sample_input(), dims)
+                .with_folding(false)
+                .run(4)
+                .active();
+            assert_eq!(folded, unfolded);
+        }
+    }
+
+    #[test]
+    fn test_day17_higher_dims() {
+        assert_eq!(Pocket::new(&sample_input(), 5).run(6).active(), 5760);
+        assert_eq!(Pocket::new(&sample_input(), 6).run(6).active(), 35936);
+    }
+
+    #[test]
+    fn test_day17_long_runs() {
+        // in two dimensions the sample is a glider, which outruns an i8 eventually
+        assert_eq!(Pocket::new(&sample_input(), 2).run(600).active(), 5);
+    }
 }
@@ -1,100 +1,278 @@
 use crate::prelude::*;
 
+use std::{collections::HashMap, ops::Range};
+
 pub enum Day18 {}
 
-#[derive(Debug, Copy, Clone, PartialEq)]
-pub enum Token {
+type Span = Range<usize>;
+
+fn syntax_error(message: &'static str, span: Span) -> Error {
+    Error::Syntax { message, span }
+}
+
+fn eval_error(message: &'static str, span: Span) -> Error {
+    Error::Eval { message, span }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Op {
     Add,
+    Sub,
     Mul,
+    Div,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TokenKind {
+    Op(Op),
     Open,
     Close,
-    Num(u64),
-}
-
-fn tokenize(s: &str) -> Vec<Token> {
-    s.bytes()
-        .filter_map(|b| match b {
-            b' ' => None,
-            b'0'..=b'9' => Some(Token::Num((b - b'0') as _)),
-            b'*' => Some(Token::Mul),
-            b'+' => Some(Token::Add),
-            b'(' => Some(Token::Open),
-            b')' => Some(Token::Close),
-            _ => panic!("unexpected char: {}", b),
-        })
-        .collect()
-}
-
-// bad shunting yard
-fn eval(tokens: impl IntoIterator<Item = Token>, advanced: bool) -> u64 {
-    let mut output = Vec::new();
-    let mut ops = Vec::new();
-    for t in tokens {
-        match t {
-            n @ Token::Num(_) => output.push(n),
-            Token::Add | Token::Mul => {
-                while let Some(op) = ops.pop() {
-                    if op == Token::Open || (advanced && (t, op) == (Token::Add, Token::Mul)) {
-                        ops.push(op);
-                        break;
-                    } else {
-                        output.push(op);
-                    }
+    Num(i64),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Token {
+    kind: TokenKind,
+    span: Span,
+}
+
+fn tokenize(s: &str) -> Result<Vec<Token>, Error> {
+    let mut tokens = Vec::new();
+    let mut chars = s.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        let kind = match c {
+            c if c.is_whitespace() => continue,
+            '+' => TokenKind::Op(Op::Add),
+            '-' => TokenKind::Op(Op::Sub),
+            '*' => TokenKind::Op(Op::Mul),
+            '/' => TokenKind::Op(Op::Div),
+            '(' => TokenKind::Open,
+            ')' => TokenKind::Close,
+            '0'..='9' => {
+                let mut end = start + 1;
+                while let Some((i, '0'..='9')) = chars.peek().copied() {
+                    end = i + 1;
+                    chars.next();
                 }
-                ops.push(t);
+                let n = s[start..end]
+                    .parse()
+                    .map_err(|_| syntax_error("number too large", start..end))?;
+                tokens.push(Token {
+                    kind: TokenKind::Num(n),
+                    span: start..end,
+                });
+                continue;
             }
-            Token::Open => ops.push(Token::Open),
-            Token::Close => {
-                while let Some(op) = ops.pop() {
-                    if op == Token::Open {
-                        break;
-                    } else {
-                        output.push(op);
-                    }
+            c => {
+                return Err(syntax_error(
+                    "unexpected character",
+                    start..start + c.len_utf8(),
+                ))
+            }
+        };
+        tokens.push(Token {
+            kind,
+            span: start..start + c.len_utf8(),
+        });
+    }
+    Ok(tokens)
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Assoc {
+    Left,
+    Right,
+}
+
+/// Binary operator precedence; higher levels bind tighter. Unary `+` and `-` bind tighter
+/// than any binary operator.
+#[derive(Debug, Clone, Default)]
+pub struct Precedence(HashMap<Op, (u8, Assoc)>);
+
+impl Precedence {
+    pub fn with(mut self, op: Op, level: u8, assoc: Assoc) -> Self {
+        self.0.insert(op, (level, assoc));
+        self
+    }
+
+    pub fn all_equal() -> Self {
+        [Op::Add, Op::Sub, Op::Mul, Op::Div]
+            .iter()
+            .fold(Self::default(), |p, &op| p.with(op, 1, Assoc::Left))
+    }
+
+    pub fn addition_first() -> Self {
+        Self::all_equal()
+            .with(Op::Add, 2, Assoc::Left)
+            .with(Op::Sub, 2, Assoc::Left)
+    }
+
+    pub fn conventional() -> Self {
+        Self::all_equal()
+            .with(Op::Mul, 2, Assoc::Left)
+            .with(Op::Div, 2, Assoc::Left)
+    }
+
+    fn binding_power(&self, op: Op) -> Option<(u16, u16)> {
+        let (level, assoc) = *self.0.get(&op)?;
+        let level = u16::from(level) * 2;
+        Some(match assoc {
+            Assoc::Left => (level, level + 1),
+            Assoc::Right => (level + 1, level),
+        })
+    }
+
+    fn prefix_binding_power(&self) -> u16 {
+        self.0.values().map(|&(level, _)| level).max().unwrap_or(0) as u16 * 2 + 2
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExprKind {
+    Num(i64),
+    Neg(Box<Expr>),
+    Binary(Op, Box<Expr>, Box<Expr>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Expr {
+    kind: ExprKind,
+    span: Span,
+}
+
+impl Expr {
+    pub fn eval(&self) -> Result<i64, Error> {
+        let overflow = || eval_error("arithmetic overflow", self.span.clone());
+        match &self.kind {
+            ExprKind::Num(n) => Ok(*n),
+            ExprKind::Neg(e) => e.eval()?.checked_neg().ok_or_else(overflow),
+            ExprKind::Binary(op, a, b) => {
+                let (a, b) = (a.eval()?, b.eval()?);
+                match op {
+                    Op::Add => a.checked_add(b).ok_or_else(overflow),
+                    Op::Sub => a.checked_sub(b).ok_or_else(overflow),
+                    Op::Mul => a.checked_mul(b).ok_or_else(overflow),
+                    Op::Div if b == 0 => Err(eval_error("division by zero", self.span.clone())),
+                    Op::Div => a.checked_div(b).ok_or_else(overflow),
                 }
             }
         }
-        //println!("output: {:?}\nops: {:?}", output, ops);
-    }
-    while let Some(op) = ops.pop() {
-        output.push(op);
-    }
-    //println!("{:?}", output);
-    let mut stack = Vec::new();
-    for x in output {
-        match x {
-            Token::Num(n) => stack.push(n),
-            Token::Add | Token::Mul => {
-                let a = stack.pop().unwrap();
-                let b = stack.pop().unwrap();
-                let c = match x {
-                    Token::Add => a + b,
-                    Token::Mul => a * b,
-                    _ => unreachable!(),
+    }
+}
+
+struct Parser<'a> {
+    tokens: std::iter::Peekable<std::slice::Iter<'a, Token>>,
+    precedence: &'a Precedence,
+    end: usize,
+}
+
+impl Parser<'_> {
+    fn expr(&mut self, min_bp: u16) -> Result<Expr, Error> {
+        let mut lhs = self.operand()?;
+        loop {
+            let token = match self.tokens.peek() {
+                None => break,
+                Some(token) => *token,
+            };
+            let op = match token.kind {
+                TokenKind::Op(op) => op,
+                TokenKind::Close => break,
+                _ => return Err(syntax_error("expected operator", token.span.clone())),
+            };
+            let (lbp, rbp) = self
+                .precedence
+                .binding_power(op)
+                .ok_or_else(|| syntax_error("unsupported operator", token.span.clone()))?;
+            if lbp < min_bp {
+                break;
+            }
+            self.tokens.next();
+
+            let rhs = self.expr(rbp)?;
+            let span = lhs.span.start..rhs.span.end;
+            lhs = Expr {
+                kind: ExprKind::Binary(op, Box::new(lhs), Box::new(rhs)),
+                span,
+            };
+        }
+        Ok(lhs)
+    }
+
+    fn operand(&mut self) -> Result<Expr, Error> {
+        let token = match self.tokens.next() {
+            Some(token) => token,
+            None => return Err(syntax_error("expected expression", self.end..self.end)),
+        };
+        match token.kind {
+            TokenKind::Num(n) => Ok(Expr {
+                kind: ExprKind::Num(n),
+                span: token.span.clone(),
+            }),
+            TokenKind::Open => {
+                let inner = self.expr(0)?;
+                match self.tokens.next() {
+                    Some(close) if close.kind == TokenKind::Close => Ok(Expr {
+                        kind: inner.kind,
+                        span: token.span.start..close.span.end,
+                    }),
+                    _ => Err(syntax_error("unclosed parenthesis", token.span.clone())),
+                }
+            }
+            TokenKind::Op(op @ Op::Add) | TokenKind::Op(op @ Op::Sub) => {
+                let inner = self.expr(self.precedence.prefix_binding_power())?;
+                let span = token.span.start..inner.span.end;
+                let kind = match op {
+                    Op::Sub => ExprKind::Neg(Box::new(inner)),
+                    _ => inner.kind,
                 };
-                stack.push(c);
+                Ok(Expr { kind, span })
             }
-            _ => panic!("wut"),
+            _ => Err(syntax_error("expected expression", token.span.clone())),
         }
     }
-    stack.into_iter().exactly_one().expect("wat")
+}
+
+pub fn parse(tokens: &[Token], precedence: &Precedence) -> Result<Expr, Error> {
+    let end = tokens.last().map_or(0, |t| t.span.end);
+    let mut parser = Parser {
+        tokens: tokens.iter().peekable(),
+        precedence,
+        end,
+    };
+    let expr = parser.expr(0)?;
+    match parser.tokens.next() {
+        None => Ok(expr),
+        Some(token) => Err(syntax_error("unmatched parenthesis", token.span.clone())),
+    }
+}
+
+pub fn eval(s: &str, precedence: &Precedence) -> Result<i64, Error> {
+    parse(&tokenize(s)?, precedence)?.eval()
 }
 
 impl Challenge for Day18 {
     type Input = Vec<Vec<Token>>;
-    type Output1 = u64;
-    type Output2 = u64;
+    type Output1 = i64;
+    type Output2 = i64;
 
     fn read(data: File) -> Result<Self::Input, Error> {
-        Ok(data.lines().map_results(|s| tokenize(&s)).try_collect()?)
+        data.lines().map(|s| tokenize(&s?)).collect()
     }
 
     fn part1(input: Self::Input) -> Self::Output1 {
-        input.into_iter().map(|x| eval(x, false)).sum()
+        let precedence = Precedence::all_equal();
+        input
+            .iter()
+            .map(|x| parse(x, &precedence).unwrap().eval().unwrap())
+            .sum()
     }
 
     fn part2(input: Self::Input) -> Self::Output2 {
-        input.into_iter().map(|x| eval(x, true)).sum()
+        let precedence = Precedence::addition_first();
+        input
+            .iter()
+            .map(|x| parse(x, &precedence).unwrap().eval().unwrap())
+            .sum()
     }
 }
 
@@ -102,7 +280,7 @@ impl Challenge for Day18 {
 mod test {
     use super::*;
 
-    fn sample_input() -> <Day18 as Challenge>::Input {
+    fn sample_input() -> [&'static str; 6] {
         [
             "1 + 2 * 3 + 4 * 5 + 6",
             "1 + (2 * 3) + (4 * (5 + 6))",
@@ -111,17 +289,19 @@ mod test {
             "5 * 9 * (7 * 3 * 3 + 9 * 3 + (8 + 6 * 4))",
             "((2 + 4 * 9) * (6 + 9 * 8 + 6) + 6) + 2 + 4 * 2",
         ]
-        .map(tokenize)
-        .to_vec()
+    }
+
+    fn eval_all(precedence: Precedence) -> Vec<i64> {
+        sample_input()
+            .iter()
+            .map(|s| eval(s, &precedence).unwrap())
+            .collect()
     }
 
     #[test]
     fn test_day18_part1() {
         assert_eq!(
-            sample_input()
-                .into_iter()
-                .map(|x| eval(x, false))
-                .collect_vec(),
+            eval_all(Precedence::all_equal()),
             [71, 51, 26, 437, 12240, 13632]
         );
     }
@@ -129,11 +309,43 @@ mod test {
     #[test]
     fn test_day18_part2() {
         assert_eq!(
-            sample_input()
-                .into_iter()
-                .map(|x| eval(x, true))
-                .collect_vec(),
+            eval_all(Precedence::addition_first()),
             [231, 51, 46, 1445, 669060, 23340]
         );
     }
+
+    #[test]
+    fn test_day18_custom_precedence() {
+        let conventional = Precedence::conventional();
+        assert_eq!(eval("12 + 3 * 10 - 40 / 4", &conventional).unwrap(), 32);
+        assert_eq!(eval("-(2 + 3) * -4", &conventional).unwrap(), 20);
+        assert_eq!(eval("10 - 4 - 3", &conventional).unwrap(), 3);
+
+        let right = conventional.with(Op::Sub, 1, Assoc::Right);
+        assert_eq!(eval("10 - 4 - 3", &right).unwrap(), 9);
+
+        let no_div = Precedence::default().with(Op::Add, 1, Assoc::Left);
+        assert!(eval("1 / 1", &no_div).is_err());
+    }
+
+    #[test]
+    fn test_day18_errors() {
+        let span = |s| match eval(s, &Precedence::conventional()) {
+            Err(Error::Syntax { span, .. }) => span,
+            other => panic!("expected syntax error, got {:?}", other),
+        };
+        assert_eq!(span("1 + x"), 4..5);
+        assert_eq!(span("(1 + 2"), 0..1);
+        assert_eq!(span("1 + 2)"), 5..6);
+        assert_eq!(span("1 2"), 2..3);
+        assert_eq!(span("1 +"), 3..3);
+
+        let eval_span = |s| match eval(s, &Precedence::conventional()) {
+            Err(Error::Eval { span, .. }) => span,
+            other => panic!("expected evaluation error, got {:?}", other),
+        };
+        assert_eq!(eval_span("7 * (3 / (1 - 1))"), 4..17);
+        assert_eq!(eval_span("1 + 9223372036854775807"), 0..23);
+        assert_eq!(eval_span("-(-9223372036854775807 - 1)"), 0..27);
+    }
 }
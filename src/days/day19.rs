@@ -1,113 +1,347 @@
 use crate::prelude::*;
 
-use std::collections::HashMap;
+use regex::Regex;
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    ops::Range,
+};
 
-#[derive(Copy, Clone, Debug, PartialEq)]
-pub enum Char {
-    A,
-    B,
-}
-
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Rule {
-    Lit(Char),
+    Lit(String),
     Seq(Vec<usize>),
-    Alt(Vec<usize>, Vec<usize>),
+    Alt(Vec<Vec<usize>>),
 }
 
 impl FromStr for Rule {
     type Err = Error;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s {
-            "\"a\"" => return Ok(Rule::Lit(Char::A)),
-            "\"b\"" => return Ok(Rule::Lit(Char::B)),
-            _ => (),
-        }
-
-        if let Some(bar) = s.find('|') {
-            let left = &s[..(bar - 1)];
-            let right = &s[(bar + 2)..];
-            let l = left.split(' ').map(|s| s.parse()).try_collect()?;
-            let r = right.split(' ').map(|s| s.parse()).try_collect()?;
-            Ok(Rule::Alt(l, r))
+        let s = s.trim();
+        if let Some(lit) = s.strip_prefix('"').and_then(|s| s.strip_suffix('"')) {
+            if lit.contains('"') {
+                return Err("literals must be the entire rule".into());
+            }
+            return Ok(Rule::Lit(lit.into()));
+        }
+
+        let parse_seq = |s: &str| s.split_whitespace().map(|s| s.parse()).try_collect();
+        let mut alts: Vec<Vec<usize>> = s.split('|').map(parse_seq).try_collect()?;
+        if alts.iter().any(Vec::is_empty) {
+            return Err("empty alternative".into());
+        }
+        if alts.len() == 1 {
+            Ok(Rule::Seq(alts.remove(0)))
         } else {
-            Ok(Rule::Seq(s.split(' ').map(|s| s.parse()).try_collect()?))
+            Ok(Rule::Alt(alts))
         }
     }
 }
 
 fn parse_rule(s: &str) -> Result<(usize, Rule), Error> {
     let colon = s.find(':').ok_or("no colon")?;
-    let idx = s[..colon].parse()?;
-    let rule = s[(colon + 2)..].parse()?;
+    let idx = s[..colon].trim().parse()?;
+    let rule = s[(colon + 1)..].parse()?;
     Ok((idx, rule))
 }
 
-#[allow(dead_code)]
-fn expand_rule(rule: &Rule, rules: &HashMap<usize, Rule>) -> String {
-    match rule {
-        Rule::Lit(c) => match c {
-            Char::A => "a".into(),
-            Char::B => "b".into(),
-        },
-        Rule::Seq(seq) => seq.iter().map(|i| expand_rule(&rules[i], rules)).join(""),
-        Rule::Alt(l, r) => format!(
-            "({}|{})",
-            expand_rule(&Rule::Seq(l.clone()), rules),
-            expand_rule(&Rule::Seq(r.clone()), rules)
-        ),
-    }
-}
-
-fn match_rule<'a>(mut s: &'a str, rule: &Rule, rules: &HashMap<usize, Rule>) -> Option<&'a str> {
-    match rule {
-        Rule::Lit(Char::A) => s.strip_prefix('a'),
-        Rule::Lit(Char::B) => s.strip_prefix('b'),
-        Rule::Seq(seq) => {
-            for idx in seq {
-                s = match_rule(s, &rules[idx], rules)?;
+fn expand_rule(
+    idx: usize,
+    rules: &HashMap<usize, Rule>,
+    memo: &mut HashMap<usize, String>,
+    path: &mut Vec<usize>,
+) -> Result<String, Error> {
+    if let Some(pattern) = memo.get(&idx) {
+        return Ok(pattern.clone());
+    }
+    if let Some(i) = path.iter().position(|&r| r == idx) {
+        return Err(Error::Cycle(
+            path[i..].iter().chain([idx].iter()).join(" -> "),
+        ));
+    }
+
+    path.push(idx);
+    let mut expand_seq = |seq: &[usize]| -> Result<String, Error> {
+        seq.iter()
+            .map(|&i| expand_rule(i, rules, memo, path))
+            .collect()
+    };
+    let pattern = match rules.get(&idx).ok_or("reference to undefined rule")? {
+        Rule::Lit(s) => regex::escape(s),
+        Rule::Seq(seq) => expand_seq(seq)?,
+        Rule::Alt(alts) => {
+            let alts: Vec<String> = alts.iter().map(|alt| expand_seq(alt)).try_collect()?;
+            format!("(?:{})", alts.join("|"))
+        }
+    };
+    path.pop();
+
+    memo.insert(idx, pattern.clone());
+    Ok(pattern)
+}
+
+/// Expands a non-recursive rule set into a single regex pattern, unanchored.
+pub fn compile_pattern(rules: &HashMap<usize, Rule>, start: usize) -> Result<String, Error> {
+    expand_rule(start, rules, &mut HashMap::new(), &mut Vec::new())
+}
+
+pub fn compile_regex(rules: &HashMap<usize, Rule>, start: usize) -> Result<Regex, Error> {
+    let pattern = compile_pattern(rules, start)?;
+    Ok(Regex::new(&format!("^(?:{})$", pattern))?)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Symbol {
+    Lit(String),
+    Rule(usize),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+struct Item {
+    rule: usize,
+    alt: usize,
+    dot: usize,
+    origin: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseTree {
+    pub rule: usize,
+    pub span: Range<usize>,
+    pub children: Vec<ParseTree>,
+}
+
+impl fmt::Display for ParseTree {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.children.is_empty() {
+            return write!(f, "{}", self.rule);
+        }
+        write!(f, "({}", self.rule)?;
+        for child in &self.children {
+            write!(f, " {}", child)?;
+        }
+        write!(f, ")")
+    }
+}
+
+/// A chart of everything an Earley parse of a message found, from a given start rule.
+struct Chart<'a> {
+    grammar: &'a Grammar,
+    msg: &'a str,
+    completed: HashSet<(usize, usize, usize)>,
+}
+
+impl Chart<'_> {
+    fn derives(&self, rule: usize, span: Range<usize>) -> bool {
+        self.completed.contains(&(rule, span.start, span.end))
+    }
+
+    fn tree(
+        &self,
+        rule: usize,
+        span: Range<usize>,
+        path: &mut Vec<(usize, Range<usize>)>,
+    ) -> Option<ParseTree> {
+        if !self.derives(rule, span.clone()) || path.contains(&(rule, span.clone())) {
+            return None;
+        }
+        path.push((rule, span.clone()));
+        let tree = self.grammar.alternatives[&rule].iter().find_map(|alt| {
+            let children = self.split(alt, span.clone(), path)?;
+            Some(ParseTree {
+                rule,
+                span: span.clone(),
+                children,
+            })
+        });
+        path.pop();
+        tree
+    }
+
+    fn split(
+        &self,
+        symbols: &[Symbol],
+        span: Range<usize>,
+        path: &mut Vec<(usize, Range<usize>)>,
+    ) -> Option<Vec<ParseTree>> {
+        let (first, rest) = match symbols.split_first() {
+            None if span.is_empty() => return Some(Vec::new()),
+            None => return None,
+            Some(split) => split,
+        };
+        match first {
+            Symbol::Lit(lit) => {
+                if !self.msg.as_bytes()[span.clone()].starts_with(lit.as_bytes()) {
+                    return None;
+                }
+                self.split(rest, span.start + lit.len()..span.end, path)
             }
-            Some(s)
+            &Symbol::Rule(rule) => (span.start..=span.end).rev().find_map(|mid| {
+                let child = self.tree(rule, span.start..mid, path)?;
+                let mut children = self.split(rest, mid..span.end, path)?;
+                children.insert(0, child);
+                Some(children)
+            }),
         }
-        Rule::Alt(l, r) => match_rule(s, &Rule::Seq(l.clone()), rules)
-            .or_else(|| match_rule(s, &Rule::Seq(r.clone()), rules)),
     }
 }
 
-fn match_part2(mut s: &str, rules: &HashMap<usize, Rule>) -> bool {
-    let mut num_42 = 0;
-    while let Some(new_s) = match_rule(s, &rules[&42], rules) {
-        s = new_s;
-        num_42 += 1;
+#[derive(Debug, Clone, Default)]
+pub struct Grammar {
+    alternatives: HashMap<usize, Vec<Vec<Symbol>>>,
+}
+
+impl Grammar {
+    pub fn new(rules: &HashMap<usize, Rule>) -> Result<Self, Error> {
+        let mut alternatives = HashMap::new();
+        for (&idx, rule) in rules {
+            let alts = match rule {
+                Rule::Lit(s) => vec![vec![Symbol::Lit(s.clone())]],
+                Rule::Seq(seq) => vec![seq.iter().map(|&i| Symbol::Rule(i)).collect()],
+                Rule::Alt(alts) => alts
+                    .iter()
+                    .map(|alt| alt.iter().map(|&i| Symbol::Rule(i)).collect())
+                    .collect(),
+            };
+            alternatives.insert(idx, alts);
+        }
+
+        let undefined = alternatives
+            .values()
+            .flatten()
+            .flatten()
+            .any(|sym| match sym {
+                Symbol::Rule(i) => !alternatives.contains_key(i),
+                Symbol::Lit(_) => false,
+            });
+        if undefined {
+            return Err("reference to undefined rule".into());
+        }
+        Ok(Self { alternatives })
     }
-    // rule 8 and rule 11 each require at least one 42.
-    if num_42 < 2 {
-        return false;
+
+    fn chart<'a>(&'a self, msg: &'a str, start: usize) -> Chart<'a> {
+        let n = msg.len();
+        let mut sets: Vec<Vec<Item>> = vec![Vec::new(); n + 1];
+        let mut seen: Vec<HashSet<Item>> = vec![HashSet::new(); n + 1];
+        let mut completed = HashSet::new();
+
+        let mut add = |sets: &mut Vec<Vec<Item>>, k: usize, item: Item| {
+            if seen[k].insert(item) {
+                sets[k].push(item);
+            }
+        };
+
+        for alt in 0..self.alternatives.get(&start).map_or(0, Vec::len) {
+            let item = Item {
+                rule: start,
+                alt,
+                dot: 0,
+                origin: 0,
+            };
+            add(&mut sets, 0, item);
+        }
+
+        for k in 0..=n {
+            let mut i = 0;
+            while i < sets[k].len() {
+                let item = sets[k][i];
+                i += 1;
+                let symbols = &self.alternatives[&item.rule][item.alt];
+                match symbols.get(item.dot) {
+                    Some(Symbol::Lit(lit)) => {
+                        if msg.as_bytes()[k..].starts_with(lit.as_bytes()) {
+                            add(
+                                &mut sets,
+                                k + lit.len(),
+                                Item {
+                                    dot: item.dot + 1,
+                                    ..item
+                                },
+                            );
+                        }
+                    }
+                    Some(&Symbol::Rule(rule)) => {
+                        for alt in 0..self.alternatives[&rule].len() {
+                            add(
+                                &mut sets,
+                                k,
+                                Item {
+                                    rule,
+                                    alt,
+                                    dot: 0,
+                                    origin: k,
+                                },
+                            );
+                        }
+                        // the predicted rule may already have matched the empty string here
+                        if completed.contains(&(rule, k, k)) {
+                            add(
+                                &mut sets,
+                                k,
+                                Item {
+                                    dot: item.dot + 1,
+                                    ..item
+                                },
+                            );
+                        }
+                    }
+                    None => {
+                        completed.insert((item.rule, item.origin, k));
+                        let waiting = sets[item.origin]
+                            .iter()
+                            .filter(|w| {
+                                self.alternatives[&w.rule][w.alt].get(w.dot)
+                                    == Some(&Symbol::Rule(item.rule))
+                            })
+                            .copied()
+                            .collect_vec();
+                        for w in waiting {
+                            add(
+                                &mut sets,
+                                k,
+                                Item {
+                                    dot: w.dot + 1,
+                                    ..w
+                                },
+                            );
+                        }
+                    }
+                }
+            }
+        }
+
+        Chart {
+            grammar: self,
+            msg,
+            completed,
+        }
+    }
+
+    /// Every suffix of `msg` that could be left over after matching a prefix against `rule`.
+    pub fn remainders<'a>(&self, msg: &'a str, rule: usize) -> Vec<&'a str> {
+        let chart = self.chart(msg, rule);
+        (0..=msg.len())
+            .filter(|&end| chart.derives(rule, 0..end))
+            .map(|end| &msg[end..])
+            .collect()
     }
 
-    let mut num_31 = 0;
-    while let Some(new_s) = match_rule(s, &rules[&31], rules) {
-        s = new_s;
-        num_31 += 1;
+    pub fn matches(&self, msg: &str, rule: usize) -> bool {
+        self.chart(msg, rule).derives(rule, 0..msg.len())
     }
 
-    if s != "" {
-        // the string didn't consist entirely of (42)*(31)*, so the counts don't even matter
-        false
-    } else if num_31 > num_42 {
-        // not enough 42s to pair with the 31s, so rule 11 not matched
-        false
-    } else if num_31 == num_42 {
-        // the 42s and the 31s paired up, sure, but rule 8 requires at least one 42 for itself
-        false
-    } else if num_31 == 0 {
-        // there has to be at least one
-        false
-    } else {
-        true
+    pub fn parse_tree(&self, msg: &str, rule: usize) -> Option<ParseTree> {
+        self.chart(msg, rule)
+            .tree(rule, 0..msg.len(), &mut Vec::new())
     }
 }
 
+fn count_matches(rules: &HashMap<usize, Rule>, msgs: &[String]) -> usize {
+    let grammar = Grammar::new(rules).unwrap();
+    msgs.iter().filter(|msg| grammar.matches(msg, 0)).count()
+}
+
 pub enum Day19 {}
 
 impl Challenge for Day19 {
@@ -120,7 +354,7 @@ impl Challenge for Day19 {
         let mut rules = HashMap::new();
         for line in &mut lines {
             let line = line?;
-            if line == "" {
+            if line.is_empty() {
                 break;
             }
             let (idx, rule) = parse_rule(&line)?;
@@ -131,13 +365,13 @@ impl Challenge for Day19 {
     }
 
     fn part1((rules, msgs): Self::Input) -> Self::Output1 {
-        msgs.into_iter()
-            .filter(|msg| match_rule(&msg, &rules[&0], &rules) == Some(""))
-            .count()
+        count_matches(&rules, &msgs)
     }
 
-    fn part2((rules, msgs): Self::Input) -> Self::Output2 {
-        msgs.iter().filter(|m| match_part2(m, &rules)).count()
+    fn part2((mut rules, msgs): Self::Input) -> Self::Output2 {
+        rules.insert(8, "42 | 42 8".parse().unwrap());
+        rules.insert(11, "42 31 | 42 11 31".parse().unwrap());
+        count_matches(&rules, &msgs)
     }
 }
 
@@ -236,5 +470,76 @@ mod test {
     #[test]
     fn test_day19_part2() {
         assert_eq!(Day19::part2(sample_input2()), 12);
+        let (rules, msgs) = sample_input2();
+        assert_eq!(count_matches(&rules, &msgs), 3);
+    }
+
+    fn grammar(rules: &[&str]) -> Grammar {
+        let rules = rules.iter().map(|s| parse_rule(s)).try_collect().unwrap();
+        Grammar::new(&rules).unwrap()
+    }
+
+    #[test]
+    fn test_day19_regex() {
+        let (rules, msgs) = sample_input();
+        assert_eq!(
+            compile_pattern(&rules, 1).unwrap(),
+            "(?:(?:aa|bb)(?:ab|ba)|(?:ab|ba)(?:aa|bb))"
+        );
+        let re = compile_regex(&rules, 0).unwrap();
+        assert_eq!(msgs.iter().filter(|m| re.is_match(m)).count(), 2);
+
+        let (rules, msgs) = sample_input2();
+        let re = compile_regex(&rules, 0).unwrap();
+        let grammar = Grammar::new(&rules).unwrap();
+        for msg in &msgs {
+            assert_eq!(re.is_match(msg), grammar.matches(msg, 0));
+        }
+    }
+
+    #[test]
+    fn test_day19_regex_rejects_cycles() {
+        let (mut rules, _) = sample_input2();
+        rules.insert(8, "42 | 42 8".parse().unwrap());
+        match compile_regex(&rules, 0) {
+            Err(Error::Cycle(cycle)) => assert_eq!(cycle, "8 -> 8"),
+            other => panic!("expected a cycle, got {:?}", other),
+        }
+        rules.insert(8, "42 | 99".parse().unwrap());
+        assert!(compile_regex(&rules, 0).is_err());
+    }
+
+    #[test]
+    fn test_day19_backtracking() {
+        // a greedy matcher takes "ab" for rule 1 and then fails on the trailing "b"
+        let g = grammar(&["0: 1 2", "1: 3 | 3 2", "2: \"b\"", "3: \"ab\""]);
+        assert!(g.matches("abb", 0));
+        assert!(g.matches("ab", 1));
+        assert!(g.matches("abbb", 0));
+        assert!(!g.matches("abbbb", 0));
+        assert_eq!(g.remainders("abbb", 1), ["bb", "b"]);
+    }
+
+    #[test]
+    fn test_day19_left_recursion() {
+        let g = grammar(&["0: 0 1 | 1", "1: 2 | 3", "2: \"xy\"", "3: \"z\""]);
+        assert!(g.matches("xyzxy", 0));
+        assert!(!g.matches("xyx", 0));
+        assert_eq!(
+            g.parse_tree("xyz", 0).unwrap().to_string(),
+            "(0 (0 (1 2)) (1 3))"
+        );
+        assert!("\"a\" | \"b\"".parse::<Rule>().is_err());
+        assert!(Grammar::new(&std::iter::once((0, Rule::Seq(vec![1]))).collect()).is_err());
+    }
+
+    #[test]
+    fn test_day19_parse_tree() {
+        let (rules, _) = sample_input();
+        let g = Grammar::new(&rules).unwrap();
+        let tree = g.parse_tree("ababbb", 0).unwrap();
+        assert_eq!(tree.span, 0..6);
+        assert_eq!(tree.to_string(), "(0 4 (1 (3 5 4) (2 5 5)) 5)");
+        assert_eq!(g.parse_tree("bababa", 0), None);
     }
 }
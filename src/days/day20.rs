@@ -1,216 +1,504 @@
 use crate::prelude::*;
 
-use std::collections::HashMap;
-use std::convert::{TryFrom, TryInto};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
 
-use itertools::{iproduct, izip};
+use itertools::iproduct;
 
-#[derive(Copy, Clone)]
-pub struct Tile<const N: usize> {
-    pixels: [[bool; N]; N],
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tile {
+    size: usize,
+    pixels: Vec<Vec<bool>>,
 }
 
-impl<const N: usize> Tile<N> {
-    fn rot_r(&self) -> Self {
-        let mut pixels = [[false; N]; N];
-
-        for (x, y) in iproduct!(0..N, 0..N) {
-            pixels[y][x] = self.pixels[(N - 1) - x][y];
+impl Tile {
+    pub fn new(pixels: Vec<Vec<bool>>) -> Result<Self, Error> {
+        let size = pixels.len();
+        if size == 0 || pixels.iter().any(|row| row.len() != size) {
+            return Err("tile is not square".into());
         }
+        Ok(Self { size, pixels })
+    }
 
-        Self { pixels }
+    pub fn size(&self) -> usize {
+        self.size
     }
 
-    fn flip_v(&self) -> Self {
-        let mut pixels = [[false; N]; N];
+    fn rot_r(&self) -> Self {
+        let n = self.size;
+        let pixels = (0..n)
+            .map(|y| (0..n).map(|x| self.pixels[(n - 1) - x][y]).collect())
+            .collect();
+        Self { size: n, pixels }
+    }
 
-        for y in 0..N {
-            pixels[y] = self.pixels[(N - 1) - y];
+    fn flip_v(&self) -> Self {
+        let pixels = self.pixels.iter().rev().cloned().collect();
+        Self {
+            size: self.size,
+            pixels,
         }
-
-        Self { pixels }
     }
 
-    fn orientations(mut self) -> Vec<Self> {
-        let mut os = Vec::new();
+    pub fn orientations(&self) -> Vec<Self> {
+        let mut os = Vec::with_capacity(8);
+        let mut tile = self.clone();
         for _ in 0..4 {
-            os.push(self);
-            self = self.rot_r();
+            let next = tile.rot_r();
+            os.push(tile);
+            tile = next;
         }
-        self = self.flip_v();
+        tile = tile.flip_v();
         for _ in 0..4 {
-            os.push(self);
-            self = self.rot_r();
+            let next = tile.rot_r();
+            os.push(tile);
+            tile = next;
         }
         os
     }
 
-    fn fits_above(&self, other: &Self) -> bool {
-        self.pixels[N - 1] == other.pixels[0]
+    fn top(&self) -> Vec<bool> {
+        self.pixels[0].clone()
     }
 
-    fn fits_below(&self, other: &Self) -> bool {
-        other.fits_above(self)
+    fn bottom(&self) -> Vec<bool> {
+        self.pixels[self.size - 1].clone()
     }
 
-    fn fits_left(&self, other: &Self) -> bool {
-        (0..N).all(|y| self.pixels[y][N - 1] == other.pixels[y][0])
+    fn left(&self) -> Vec<bool> {
+        self.pixels.iter().map(|row| row[0]).collect()
     }
 
-    fn fits_right(&self, other: &Self) -> bool {
-        other.fits_left(self)
+    fn right(&self) -> Vec<bool> {
+        self.pixels.iter().map(|row| row[self.size - 1]).collect()
+    }
+
+    fn edges(&self) -> [Vec<bool>; 4] {
+        [self.top(), self.bottom(), self.left(), self.right()]
+    }
+
+    fn interior(&self) -> impl Iterator<Item = &[bool]> {
+        let n = self.size;
+        self.pixels[1..n - 1].iter().map(move |row| &row[1..n - 1])
     }
 }
 
-impl<const N: usize> std::fmt::Display for Tile<N> {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        let mut buf = vec![b'.'; (N + 1) * N - 1];
-        for (x, y) in iproduct!(0..N, 0..N) {
-            if self.pixels[y][x] {
-                buf[(N + 1) * y + x] = b'#';
-            }
-        }
-        for y in 0..(N - 1) {
-            buf[(N + 1) * y + N] = b'\n';
+impl fmt::Display for Tile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let rows = self.pixels.iter().map(|row| {
+            row.iter()
+                .map(|&p| if p { '#' } else { '.' })
+                .collect::<String>()
+        });
+        write!(f, "{}", rows.format("\n"))
+    }
+}
+
+/// An edge and its reverse describe the same border once tiles may be flipped.
+fn canonical_edge(edge: Vec<bool>) -> Vec<bool> {
+    let reversed = edge.iter().rev().copied().collect_vec();
+    edge.min(reversed)
+}
+
+fn parse(s: &str) -> Result<<Day20 as Challenge>::Input, Error> {
+    let mut tiles = HashMap::new();
+    for chunk in s.split("\n\n").filter(|c| !c.trim().is_empty()) {
+        let mut lines = chunk.lines();
+        let id = lines
+            .next()
+            .and_then(|l| l.trim().strip_prefix("Tile "))
+            .and_then(|l| l.strip_suffix(':'))
+            .ok_or("malformed tile header")?
+            .parse()?;
+        let tile = Tile::new(
+            lines
+                .map(|s| s.trim().bytes().map(|c| c == b'#').collect())
+                .collect(),
+        )?;
+
+        if tiles.insert(id, tile).is_some() {
+            return Err("duplicate tile id".into());
         }
-        f.write_str(std::str::from_utf8(&buf).unwrap())
     }
+    Ok(tiles)
 }
 
-impl<const N: usize> TryFrom<Vec<Vec<bool>>> for Tile<N> {
-    type Error = &'static str; // whatever
+/// Tiles laid out in a square grid, row-major, in their final orientations.
+#[derive(Debug, Clone)]
+pub struct Assembly {
+    side: usize,
+    tiles: Vec<(u64, Tile)>,
+}
 
-    fn try_from(value: Vec<Vec<bool>>) -> Result<Self, Self::Error> {
-        let pixels = value
-            .into_iter()
-            .map(|row| row.try_into().map_err(|_| "bad width"))
-            .collect::<Result<Vec<[bool; N]>, &str>>()?
-            .try_into()
-            .map_err(|_| "bad height")?;
+impl Assembly {
+    pub fn side(&self) -> usize {
+        self.side
+    }
 
-        Ok(Self { pixels })
+    pub fn get(&self, x: usize, y: usize) -> Option<&(u64, Tile)> {
+        if x < self.side {
+            self.tiles.get(y * self.side + x)
+        } else {
+            None
+        }
+    }
+
+    pub fn corners(&self) -> [u64; 4] {
+        let n = self.side - 1;
+        [(0, 0), (n, 0), (0, n), (n, n)].map(|(x, y)| self.get(x, y).unwrap().0)
+    }
+
+    /// Joins the tiles with their borders removed.
+    pub fn stitch(&self) -> Tile {
+        let pixels = self
+            .tiles
+            .chunks(self.side)
+            .flat_map(|row| {
+                let interiors = row
+                    .iter()
+                    .map(|(_, t)| t.interior().collect_vec())
+                    .collect_vec();
+                (0..interiors[0].len()).map(move |y| {
+                    interiors
+                        .iter()
+                        .flat_map(|t| t[y].iter().copied())
+                        .collect()
+                })
+            })
+            .collect();
+        Tile::new(pixels).unwrap()
     }
 }
 
-fn parse(s: &str) -> Result<<Day20 as Challenge>::Input, Error> {
-    let mut tiles = HashMap::new();
-    for mut chunk in &s.split('\n').chunks(12) {
-        let id_line = chunk.next().ok_or("no id???")?;
-        if id_line.is_empty() {
-            break;
+struct Assembler {
+    side: usize,
+    orientations: HashMap<u64, Vec<Tile>>,
+    by_edge: HashMap<Vec<bool>, Vec<u64>>,
+    /// Tiles with at least two edges that match no other tile, sorted by id.
+    corners: Vec<u64>,
+}
+
+impl Assembler {
+    fn new(tiles: &HashMap<u64, Tile>) -> Result<Self, Error> {
+        let side = (1..=tiles.len())
+            .find(|s| s * s >= tiles.len())
+            .filter(|s| s * s == tiles.len())
+            .ok_or("tile count is not a square")?;
+        let size = tiles.values().next().map_or(3, Tile::size);
+        if size < 3 {
+            return Err("tiles need a border around their interior".into());
+        }
+        if tiles.values().any(|t| t.size != size) {
+            return Err("tiles have different sizes".into());
         }
-        let id = id_line[5..(id_line.len() - 1)].parse()?;
-        let tile = chunk
-            .take(10)
-            .map(|s| s.bytes().map(|c| c == b'#').collect_vec())
-            .collect_vec()
-            .try_into()?;
 
-        tiles.insert(id, tile);
+        // buckets are filled in id order so that the search doesn't depend on hash order
+        let mut by_edge: HashMap<_, Vec<_>> = HashMap::new();
+        for (&id, tile) in tiles.iter().sorted_by_key(|&(&id, _)| id) {
+            for edge in tile.edges().iter() {
+                let bucket = by_edge.entry(canonical_edge(edge.clone())).or_default();
+                if bucket.last() != Some(&id) {
+                    bucket.push(id);
+                }
+            }
+        }
+
+        let orientations = tiles
+            .iter()
+            .map(|(&id, tile)| (id, tile.orientations()))
+            .collect();
+
+        let mut assembler = Self {
+            side,
+            orientations,
+            by_edge,
+            corners: Vec::new(),
+        };
+        assembler.corners = tiles
+            .iter()
+            .filter(|(_, tile)| {
+                let edges = tile.edges();
+                edges.iter().filter(|e| assembler.unmatched(e)).count() >= 2
+            })
+            .map(|(&id, _)| id)
+            .sorted()
+            .collect();
+        Ok(assembler)
+    }
+
+    /// Whether no tile other than the one it came from has this edge.
+    fn unmatched(&self, edge: &[bool]) -> bool {
+        self.by_edge[&canonical_edge(edge.to_vec())].len() == 1
     }
-    Ok(tiles)
-}
 
-fn assemble(
-    mut tiles: HashMap<u64, Tile<10>>,
-) -> (HashMap<(i8, i8), Tile<10>>, HashMap<(i8, i8), u64>) {
-    let mut img = HashMap::new();
-    let mut ids = HashMap::new();
-
-    let first_id = tiles.keys().copied().next().unwrap();
-    ids.insert((0, 0), first_id);
-
-    let first_tile = tiles.remove(&first_id).unwrap();
-    img.insert((0, 0), first_tile);
-
-    while tiles.len() > 0 {
-        let mut failed = HashMap::new();
-
-        'next_tile: for (id, tile) in tiles {
-            for piece in tile.orientations() {
-                'next_pos: for (pos, o) in &img {
-                    let (x, y) = *pos;
-                    let (above, below, left, right) =
-                        ((x, y - 1), (x, y + 1), (x - 1, y), (x + 1, y));
-
-                    let placement = if !img.contains_key(&above) && piece.fits_above(o) {
-                        above
-                    } else if !img.contains_key(&below) && piece.fits_below(o) {
-                        below
-                    } else if !img.contains_key(&left) && piece.fits_left(o) {
-                        left
-                    } else if !img.contains_key(&right) && piece.fits_right(o) {
-                        right
-                    } else {
-                        continue 'next_pos;
-                    };
-                    ids.insert(placement, id);
-                    img.insert(placement, piece);
-                    continue 'next_tile;
+    fn candidates(&self, placed: &[(u64, Tile)], corners_only: bool) -> Vec<u64> {
+        let i = placed.len();
+        let column = i % self.side;
+        let neighbour = if column > 0 {
+            Some(placed[i - 1].1.right())
+        } else if i >= self.side {
+            Some(placed[i - self.side].1.bottom())
+        } else {
+            None
+        };
+        match neighbour {
+            Some(edge) => self
+                .by_edge
+                .get(&canonical_edge(edge))
+                .cloned()
+                .unwrap_or_default(),
+            None if corners_only => self.corners.clone(),
+            None => self.orientations.keys().copied().sorted().collect(),
+        }
+    }
+
+    fn fits(&self, placed: &[(u64, Tile)], tile: &Tile, corners_only: bool) -> bool {
+        let i = placed.len();
+        if i == 0 && corners_only {
+            return self.unmatched(&tile.top()) && self.unmatched(&tile.left());
+        }
+        let column = i % self.side;
+        (column == 0 || placed[i - 1].1.right() == tile.left())
+            && (i < self.side || placed[i - self.side].1.bottom() == tile.top())
+    }
+
+    /// With `corners_only`, the top-left position is only tried with a corner tile turned so
+    /// that its unmatched edges face outwards.
+    fn solve(
+        &self,
+        placed: &mut Vec<(u64, Tile)>,
+        used: &mut HashSet<u64>,
+        corners_only: bool,
+    ) -> bool {
+        if placed.len() == self.side * self.side {
+            return true;
+        }
+        for id in self.candidates(placed, corners_only) {
+            if used.contains(&id) {
+                continue;
+            }
+            for tile in &self.orientations[&id] {
+                if !self.fits(placed, tile, corners_only) {
+                    continue;
+                }
+                placed.push((id, tile.clone()));
+                used.insert(id);
+                if self.solve(placed, used, corners_only) {
+                    return true;
                 }
+                used.remove(&id);
+                placed.pop();
             }
+        }
+        false
+    }
+}
+
+pub fn assemble(tiles: &HashMap<u64, Tile>) -> Result<Assembly, Error> {
+    let assembler = Assembler::new(tiles)?;
+    let mut placed = Vec::with_capacity(tiles.len());
+    // short edges can match by chance, hiding the real corners, so fall back to every tile
+    let solved = assembler.solve(&mut placed, &mut HashSet::new(), true)
+        || assembler.solve(&mut placed, &mut HashSet::new(), false);
+    if !solved {
+        return Err("tiles cannot be assembled".into());
+    }
+    Ok(Assembly {
+        side: assembler.side,
+        tiles: placed,
+    })
+}
+
+/// The cells a pattern requires to be set, relative to its top-left corner.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pattern {
+    width: usize,
+    height: usize,
+    cells: Vec<(usize, usize)>,
+}
+
+impl Pattern {
+    pub fn sea_monster() -> Self {
+        "                  # \n#    ##    ##    ###\n #  #  #  #  #  #   "
+            .parse()
+            .unwrap()
+    }
+
+    fn from_cells(width: usize, height: usize, mut cells: Vec<(usize, usize)>) -> Self {
+        cells.sort_unstable();
+        Self {
+            width,
+            height,
+            cells,
+        }
+    }
+
+    fn rot_r(&self) -> Self {
+        let cells = self
+            .cells
+            .iter()
+            .map(|&(x, y)| (self.height - 1 - y, x))
+            .collect();
+        Self::from_cells(self.height, self.width, cells)
+    }
 
-            failed.insert(id, tile);
+    fn flip_v(&self) -> Self {
+        let cells = self
+            .cells
+            .iter()
+            .map(|&(x, y)| (x, self.height - 1 - y))
+            .collect();
+        Self::from_cells(self.width, self.height, cells)
+    }
+
+    /// The distinct orientations of the pattern; symmetric patterns have fewer than 8.
+    pub fn orientations(&self) -> Vec<Self> {
+        let mut os: Vec<Self> = Vec::with_capacity(8);
+        let mut pattern = self.clone();
+        for i in 0..8 {
+            if i == 4 {
+                pattern = pattern.flip_v();
+            }
+            let next = pattern.rot_r();
+            if !os.contains(&pattern) {
+                os.push(pattern);
+            }
+            pattern = next;
         }
+        os
+    }
 
-        tiles = failed;
+    pub fn cells(&self) -> &[(usize, usize)] {
+        &self.cells
     }
+}
 
-    (img, ids)
+impl std::str::FromStr for Pattern {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let cells = s
+            .lines()
+            .enumerate()
+            .flat_map(|(y, line)| {
+                line.bytes()
+                    .enumerate()
+                    .filter(|&(_, c)| c == b'#')
+                    .map(move |(x, _)| (x, y))
+            })
+            .collect_vec();
+        if cells.is_empty() {
+            return Err("pattern has no cells".into());
+        }
+        // trailing blank lines would otherwise keep the pattern off the bottom rows
+        let width = cells.iter().map(|&(x, _)| x + 1).max().unwrap();
+        let height = cells.iter().map(|&(_, y)| y + 1).max().unwrap();
+        Ok(Self::from_cells(width, height, cells))
+    }
+}
+
+/// An occurrence of a pattern in one of its orientations, with `(x, y)` its top-left corner.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Match {
+    pub x: usize,
+    pub y: usize,
+    pub pattern: Pattern,
 }
 
-fn row_match(pixels: &[bool], monster: &[u8]) -> bool {
-    pixels
-        .iter()
-        .zip(monster.iter())
-        .all(|(v, c)| *c == b' ' || *v)
+impl Match {
+    pub fn cells(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        self.pattern
+            .cells
+            .iter()
+            .map(move |&(x, y)| (self.x + x, self.y + y))
+    }
 }
 
-fn do_part2<const N: usize>(img: Tile<N>) -> usize {
-    let num_hash = img
-        .pixels
-        .iter()
-        .flat_map(|row| row.iter())
-        .filter(|x| **x)
-        .count();
-
-    let monster = [
-        b"                  # ",
-        b"#    ##    ##    ###",
-        b" #  #  #  #  #  #   ",
-    ];
-
-    for img in img.orientations() {
-        let mut monster_count = 0;
-        for rows in img.pixels.windows(3) {
-            let (t, m, b) = match rows {
-                [t, m, b] => (t, m, b),
-                _ => unreachable!(),
-            };
-
-            let w = monster[0].len();
-            for (t, m, b) in izip!(t.windows(w), m.windows(w), b.windows(w)) {
-                if izip!(&[t, m, b], &monster).all(|(p, m)| row_match(p, *m)) {
-                    monster_count += 1;
+impl Tile {
+    /// Every placement of every orientation of `pattern`, including overlapping ones.
+    pub fn find(&self, pattern: &Pattern) -> Vec<Match> {
+        let mut matches = Vec::new();
+        for pattern in pattern.orientations() {
+            if pattern.width > self.size || pattern.height > self.size {
+                continue;
+            }
+            for (y, x) in iproduct!(
+                0..=self.size - pattern.height,
+                0..=self.size - pattern.width
+            ) {
+                if pattern
+                    .cells
+                    .iter()
+                    .all(|&(dx, dy)| self.pixels[y + dy][x + dx])
+                {
+                    matches.push(Match {
+                        x,
+                        y,
+                        pattern: pattern.clone(),
+                    });
                 }
             }
         }
+        matches
+    }
 
-        if monster_count != 0 {
-            return num_hash - 15 * monster_count;
-        }
+    fn covered(matches: &[Match]) -> HashSet<(usize, usize)> {
+        matches.iter().flat_map(Match::cells).collect()
+    }
+
+    /// The number of set pixels not part of any match; overlapping matches count cells once.
+    pub fn roughness(&self, matches: &[Match]) -> usize {
+        let covered = Self::covered(matches);
+        iproduct!(0..self.size, 0..self.size)
+            .filter(|&(y, x)| self.pixels[y][x] && !covered.contains(&(x, y)))
+            .count()
     }
 
-    println!("no monsters found");
-    num_hash
+    /// Renders the image like `Display`, with matched cells drawn as `O`.
+    pub fn highlight(&self, matches: &[Match]) -> String {
+        let covered = Self::covered(matches);
+        (0..self.size)
+            .map(|y| {
+                (0..self.size)
+                    .map(|x| match (covered.contains(&(x, y)), self.pixels[y][x]) {
+                        (true, _) => 'O',
+                        (false, true) => '#',
+                        (false, false) => '.',
+                    })
+                    .collect::<String>()
+            })
+            .join("\n")
+    }
+}
+
+/// The part 2 answer, keeping the image so that the monsters can be shown.
+pub struct SeaMonsters {
+    image: Tile,
+    matches: Vec<Match>,
+}
+
+impl SeaMonsters {
+    pub fn roughness(&self) -> usize {
+        self.image.roughness(&self.matches)
+    }
+}
+
+impl Answer for SeaMonsters {
+    fn canonical(&self) -> String {
+        self.roughness().to_string()
+    }
+
+    fn render(&self) -> Option<String> {
+        Some(self.image.highlight(&self.matches))
+    }
 }
 
 pub enum Day20 {}
 
 impl Challenge for Day20 {
-    type Input = HashMap<u64, Tile<10>>;
+    type Input = HashMap<u64, Tile>;
     type Output1 = u64;
-    type Output2 = usize;
+    type Output2 = SeaMonsters;
 
     fn read(mut data: File) -> Result<Self::Input, Error> {
         let mut s = String::new();
@@ -219,38 +507,13 @@ impl Challenge for Day20 {
     }
 
     fn part1(input: Self::Input) -> Self::Output1 {
-        let (_, ids) = assemble(input);
-
-        let (min_x, max_x) = ids.keys().map(|(x, _)| *x).minmax().into_option().unwrap();
-        let (min_y, max_y) = ids.keys().map(|(_, y)| *y).minmax().into_option().unwrap();
-
-        ids[&(min_x, min_y)] * ids[&(min_x, max_y)] * ids[&(max_x, min_y)] * ids[&(max_x, max_y)]
+        assemble(&input).unwrap().corners().iter().product()
     }
 
     fn part2(input: Self::Input) -> Self::Output2 {
-        let (img, _) = assemble(input);
-
-        let (min_x, max_x) = img.keys().map(|(x, _)| *x).minmax().into_option().unwrap();
-        let (min_y, max_y) = img.keys().map(|(_, y)| *y).minmax().into_option().unwrap();
-
-        let mut stitched = Vec::new();
-
-        for tile_y in min_y..=max_y {
-            let mut rows = vec![Vec::new(); 8];
-            for tile_x in min_x..=max_x {
-                let tile = &img[&(tile_x, tile_y)];
-                for y in 1..9 {
-                    rows[y - 1].extend(tile.pixels[y][1..9].iter().copied());
-                }
-            }
-            stitched.extend(rows);
-        }
-
-        match stitched.len() {
-            24 => do_part2::<24>(stitched.try_into().unwrap()),
-            96 => do_part2::<96>(stitched.try_into().unwrap()),
-            _ => panic!("unsupported dimensions"),
-        }
+        let image = assemble(&input).unwrap().stitch();
+        let matches = image.find(&Pattern::sea_monster());
+        SeaMonsters { image, matches }
     }
 }
 
@@ -258,9 +521,162 @@ impl Challenge for Day20 {
 mod test {
     use super::*;
 
+    const SAMPLE: &str = "\
+Tile 2311:
+..##.#..#.
+##..#.....
+#...##..#.
+####.#...#
+##.##.###.
+##...#.###
+.#.#.#..##
+..#....#..
+###...#.#.
+..###..###
+
+Tile 1951:
+#.##...##.
+#.####...#
+.....#..##
+#...######
+.##.#....#
+.###.#####
+###.##.##.
+.###....#.
+..#.#..#.#
+#...##.#..
+
+Tile 1171:
+####...##.
+#..##.#..#
+##.#..#.#.
+.###.####.
+..###.####
+.##....##.
+.#...####.
+#.##.####.
+####..#...
+.....##...
+
+Tile 1427:
+###.##.#..
+.#..#.##..
+.#.##.#..#
+#.#.#.##.#
+....#...##
+...##..##.
+...#.#####
+.#.####.#.
+..#..###.#
+..##.#..#.
+
+Tile 1489:
+##.#.#....
+..##...#..
+.##..##...
+..#...#...
+#####...#.
+#..#.#.#.#
+...#.#.#..
+##.#...##.
+..##.##.##
+###.##.#..
+
+Tile 2473:
+#....####.
+#..#.##...
+#.##..#...
+######.#.#
+.#...#.#.#
+.#########
+.###.#..#.
+########.#
+##...##.#.
+..###.#.#.
+
+Tile 2971:
+..#.#....#
+#...###...
+#.#.###...
+##.##..#..
+.#####..##
+.#..####.#
+#..#.#..#.
+..####.###
+..#.#.###.
+...#.#.#.#
+
+Tile 2729:
+...#.#.#.#
+####.#....
+..#.#.....
+....#..#.#
+.##..##.#.
+.#.####...
+####.#.#..
+##.####...
+##..#.##..
+#.##...##.
+
+Tile 3079:
+#.#.#####.
+.#..######
+..#.......
+######....
+####.#..#.
+.#...#.##.
+#.#####.##
+..#.###...
+..#.......
+..#.###...
+";
+
     fn sample_input() -> <Day20 as Challenge>::Input {
-        let s = std::fs::read_to_string("input/day20_sample.txt").unwrap();
-        parse(&s).unwrap()
+        parse(SAMPLE).unwrap()
+    }
+
+    /// Cuts a pseudo-random image into `side * side` overlapping tiles, each randomly oriented.
+    fn synthetic(side: usize, size: usize) -> (HashMap<u64, Tile>, Tile) {
+        let mut state = 0x2545_f491_u64;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+
+        let full = side * (size - 1) + 1;
+        let image = (0..full)
+            .map(|_| (0..full).map(|_| next() % 2 == 0).collect_vec())
+            .collect_vec();
+
+        let mut tiles = HashMap::new();
+        let mut expected = Vec::new();
+        for ty in 0..side {
+            let mut row = Vec::new();
+            for tx in 0..side {
+                let tile = Tile::new(
+                    (0..size)
+                        .map(|y| image[ty * (size - 1) + y][tx * (size - 1)..][..size].to_vec())
+                        .collect(),
+                )
+                .unwrap();
+                row.push(tile.clone());
+                let orientation = (next() % 8) as usize;
+                tiles.insert(next(), tile.orientations().swap_remove(orientation));
+            }
+            expected.push((0, row));
+        }
+
+        let stitched = Assembly {
+            side,
+            tiles: expected
+                .into_iter()
+                .flat_map(|(id, row)| row.into_iter().map(move |t| (id, t)))
+                .collect(),
+        }
+        .stitch();
+        (tiles, stitched)
     }
 
     #[test]
@@ -270,6 +686,102 @@ mod test {
 
     #[test]
     fn test_day20_part2() {
-        assert_eq!(Day20::part2(sample_input()), 273);
+        let answer = Day20::part2(sample_input());
+        assert_eq!(answer.roughness(), 273);
+        assert!(answer.check("273"));
+        assert_eq!(answer.render().unwrap().lines().count(), 24);
+    }
+
+    #[test]
+    fn test_day20_stitch() {
+        let image = assemble(&sample_input()).unwrap().stitch();
+        assert_eq!(image.size(), 24);
+        assert_eq!(image.pixels.iter().flatten().filter(|&&p| p).count(), 303);
+    }
+
+    #[test]
+    fn test_day20_any_size() {
+        for &(side, size) in &[(1, 4), (2, 5), (4, 3), (3, 16), (5, 12)] {
+            let (tiles, expected) = synthetic(side, size);
+            let assembly = assemble(&tiles).unwrap();
+            for (y, x) in iproduct!(0..side, 0..side) {
+                let tile = &assembly.get(x, y).unwrap().1;
+                if let Some((_, right)) = assembly.get(x + 1, y) {
+                    assert_eq!(tile.right(), right.left());
+                }
+                if let Some((_, below)) = assembly.get(x, y + 1) {
+                    assert_eq!(tile.bottom(), below.top());
+                }
+            }
+
+            let image = assembly.stitch();
+            assert_eq!(image.size(), side * (size - 2));
+            // small edges can match in more than one way, so only long ones pin down the image
+            if size >= 12 {
+                assert!(expected.orientations().contains(&image));
+            }
+        }
+    }
+
+    #[test]
+    fn test_day20_pattern_search() {
+        let image = assemble(&sample_input()).unwrap().stitch();
+        let monster = Pattern::sea_monster();
+        assert_eq!(monster.orientations().len(), 8);
+
+        let matches = image.find(&monster);
+        assert_eq!(matches.len(), 2);
+        assert!(matches.iter().all(|m| m.pattern == matches[0].pattern));
+        assert_eq!(image.roughness(&matches), 273);
+
+        let rendered = image.highlight(&matches);
+        assert_eq!(rendered.matches('O').count(), 30);
+        assert_eq!(rendered.matches('#').count(), 273);
+    }
+
+    #[test]
+    fn test_day20_overlapping_matches() {
+        let image = Tile::new(
+            ["###.", "###.", "....", "...."]
+                .iter()
+                .map(|r| r.bytes().map(|c| c == b'#').collect())
+                .collect(),
+        )
+        .unwrap();
+
+        let bar: Pattern = "##".parse().unwrap();
+        assert_eq!(bar.orientations().len(), 2);
+        let matches = image.find(&bar);
+        assert_eq!(matches.len(), 7);
+        assert_eq!(image.roughness(&matches), 0);
+
+        let corner: Pattern = "#.\n##".parse().unwrap();
+        let matches = image.find(&corner);
+        assert_eq!(matches.len(), 8);
+        assert_eq!(image.highlight(&matches[..1]), "O##.\nOO#.\n....\n....");
+
+        assert!("...".parse::<Pattern>().is_err());
+        let trailing: Pattern = "#.\n##\n\n".parse().unwrap();
+        assert_eq!(trailing, corner);
+        let dot = Tile::new(vec![vec![true]]).unwrap();
+        assert_eq!(dot.find(&"#\n\n".parse().unwrap()).len(), 1);
+        assert!(image.find(&Pattern::sea_monster()).is_empty());
+    }
+
+    #[test]
+    fn test_day20_errors() {
+        let mut tiles = sample_input();
+        tiles.remove(&2311);
+        assert!(assemble(&tiles).is_err());
+
+        let mixed = parse("Tile 1:\n###\n...\n###\n\nTile 2:\n####\n....\n....\n####\n");
+        assert!(assemble(&mixed.unwrap()).is_err());
+        assert!(parse("Tile 1:\n##\n#\n").is_err());
+        assert!(assemble(&parse("Tile 1:\n##\n#.\n").unwrap()).is_err());
+        assert!(Tile::new(Vec::new()).is_err());
+
+        let small = Tile::new(vec![vec![true, false], vec![false, true]]).unwrap();
+        let tiles = (0..4).map(|id| (id, small.clone())).collect();
+        assert!(assemble(&tiles).is_err());
     }
 }
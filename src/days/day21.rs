@@ -1,99 +1,119 @@
-use crate::prelude::*;
+use crate::{
+    assignment::{self, Candidates},
+    prelude::*,
+};
 
 use std::collections::{HashMap, HashSet};
 
-type Row = (HashSet<String>, HashSet<String>);
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Food {
+    ingredients: HashSet<String>,
+    allergens: HashSet<String>,
+}
 
-pub enum Day21 {}
+impl FromStr for Food {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (ingredients, allergens) = match s.find('(') {
+            None => (s, None),
+            Some(open) => {
+                let list = s[open..]
+                    .trim_end()
+                    .strip_prefix("(contains ")
+                    .and_then(|l| l.strip_suffix(')'))
+                    .ok_or("malformed allergen list")?;
+                (&s[..open], Some(list))
+            }
+        };
 
-fn parse_line(s: &str) -> Row {
-    let mut words = s.split(' ');
-    let mut ingredients = HashSet::new();
-    for ingredient in &mut words {
-        if ingredient == "(contains" {
-            break;
+        let ingredients: HashSet<String> =
+            ingredients.split_whitespace().map(String::from).collect();
+        if ingredients.is_empty() {
+            return Err("food has no ingredients".into());
         }
-        ingredients.insert(ingredient.into());
+
+        let allergens = match allergens {
+            None => HashSet::new(),
+            Some(list) => list
+                .split(',')
+                .map(|a| match a.trim() {
+                    "" => Err("empty allergen".into()),
+                    a if a.contains(char::is_whitespace) => Err("malformed allergen".into()),
+                    a => Ok(a.to_string()),
+                })
+                .collect::<Result<_, Error>>()?,
+        };
+
+        Ok(Self {
+            ingredients,
+            allergens,
+        })
     }
-    let allergens = words
-        .map(|x| x.strip_suffix(|c| ",)".contains(c)).unwrap_or(x).into())
-        .collect();
-    (ingredients, allergens)
 }
 
-fn shared(
-    input: &<Day21 as Challenge>::Input,
-) -> (HashSet<String>, HashMap<String, HashSet<String>>) {
-    let (all_ingredients, all_allergens) = {
-        let (mut ingredients, mut allergens) = (HashSet::new(), HashSet::new());
-        for (i, a) in input {
-            ingredients.extend(i.iter().cloned());
-            allergens.extend(a.iter().cloned());
-        }
-        (ingredients, allergens)
-    };
-
-    let mut mappings = all_allergens
-        .iter()
-        .map(|k| (k.clone(), all_ingredients.clone()))
-        .collect::<HashMap<String, HashSet<String>>>();
+pub enum Day21 {}
 
-    for (ingr, alle) in input {
-        for a in alle {
-            let m = mappings.get_mut(a).unwrap();
-            *m = &*m & ingr;
+/// The ingredients each allergen could be in, given that it is in every food listing it.
+pub fn allergen_candidates(foods: &[Food]) -> Candidates<String, String> {
+    let mut candidates: Candidates<String, String> = HashMap::new();
+    for food in foods {
+        for allergen in &food.allergens {
+            candidates
+                .entry(allergen.clone())
+                .and_modify(|c| *c = &*c & &food.ingredients)
+                .or_insert_with(|| food.ingredients.clone());
         }
     }
+    candidates
+}
 
-    let mut safe_ingredients = all_ingredients.clone();
-    for ingr in mappings.values() {
-        safe_ingredients = &safe_ingredients - ingr;
-    }
+pub fn safe_ingredients(foods: &[Food]) -> HashSet<String> {
+    let unsafe_ingredients: HashSet<_> =
+        allergen_candidates(foods).into_values().flatten().collect();
+    foods
+        .iter()
+        .flat_map(|f| &f.ingredients)
+        .filter(|i| !unsafe_ingredients.contains(*i))
+        .cloned()
+        .collect()
+}
+
+/// Every way of pinning each allergen to a distinct ingredient.
+pub fn allergen_assignments(foods: &[Food]) -> Vec<HashMap<String, String>> {
+    assignment::all(&allergen_candidates(foods))
+}
 
-    (safe_ingredients, mappings)
+pub fn dangerous_list(foods: &[Food]) -> Result<String, Error> {
+    let assignment = assignment::unique(&allergen_candidates(foods))?;
+    Ok(assignment
+        .into_iter()
+        .sorted()
+        .map(|(_, ingredient)| ingredient)
+        .join(","))
 }
 
 impl Challenge for Day21 {
-    type Input = Vec<Row>;
+    type Input = Vec<Food>;
     type Output1 = usize;
     type Output2 = String;
 
     fn read(data: File) -> Result<Self::Input, Error> {
-        Ok(data.lines().map_results(|s| parse_line(&s)).try_collect()?)
+        data.parse_lines()
     }
 
     fn part1(input: Self::Input) -> Self::Output1 {
-        let (safe_ingredients, _) = shared(&input);
+        let safe_ingredients = safe_ingredients(&input);
 
         input
             .iter()
-            .flat_map(|v| &v.0)
+            .flat_map(|f| &f.ingredients)
             .filter(|ing| safe_ingredients.contains(ing.as_str()))
             .count()
     }
 
     fn part2(input: Self::Input) -> Self::Output2 {
-        let (safe_ingredients, mut mappings) = shared(&input);
-
-        for ingrs in mappings.values_mut() {
-            *ingrs = &*ingrs - &safe_ingredients;
-        }
-
-        let mut list = Vec::new();
-        while !mappings.is_empty() {
-            for allergen in mappings.keys().cloned().collect_vec() {
-                if let Some(ingredient) = mappings[&allergen].iter().exactly_one().ok().cloned() {
-                    mappings.remove(&allergen);
-                    for ingrs in mappings.values_mut() {
-                        ingrs.remove(&ingredient);
-                    }
-                    list.push((allergen, ingredient));
-                }
-            }
-        }
-
-        list.sort_unstable_by(|a, b| a.0.cmp(&b.0));
-        list.into_iter().map(|(_, v)| v).join(",")
+        dangerous_list(&input).unwrap()
     }
 }
 
@@ -101,15 +121,17 @@ impl Challenge for Day21 {
 mod test {
     use super::*;
 
+    fn parse_all(lines: &[&str]) -> Vec<Food> {
+        lines.iter().map(|s| s.parse().unwrap()).collect()
+    }
+
     fn sample_input() -> <Day21 as Challenge>::Input {
-        [
+        parse_all(&[
             "mxmxvkd kfcds sqjhc nhms (contains dairy, fish)",
             "trh fvjkl sbzzf mxmxvkd (contains dairy)",
             "sqjhc fvjkl (contains soy)",
             "sqjhc mxmxvkd sbzzf (contains fish)",
-        ]
-        .map(parse_line)
-        .to_vec()
+        ])
     }
 
     #[test]
@@ -121,4 +143,31 @@ mod test {
     fn test_day21_part2() {
         assert_eq!(Day21::part2(sample_input()), "mxmxvkd,sqjhc,fvjkl");
     }
+
+    #[test]
+    fn test_day21_parse() {
+        let food: Food = "a b  (contains x,y, z)".parse().unwrap();
+        assert_eq!(food.ingredients.len(), 2);
+        assert_eq!(food.allergens.len(), 3);
+        assert!("a b".parse::<Food>().unwrap().allergens.is_empty());
+
+        assert!("(contains x)".parse::<Food>().is_err());
+        assert!("a (contains x".parse::<Food>().is_err());
+        assert!("a (contains )".parse::<Food>().is_err());
+        assert!("a (contains x,, y)".parse::<Food>().is_err());
+        assert!("a (has x)".parse::<Food>().is_err());
+    }
+
+    #[test]
+    fn test_day21_ambiguous() {
+        // elimination alone used to spin forever on this
+        let foods = parse_all(&["a b (contains x, y)", "a b c"]);
+        assert_eq!(allergen_assignments(&foods).len(), 2);
+        assert!(dangerous_list(&foods).is_err());
+        assert_eq!(safe_ingredients(&foods).len(), 1);
+
+        let foods = parse_all(&["a (contains x, y)"]);
+        assert!(allergen_assignments(&foods).is_empty());
+        assert!(dangerous_list(&foods).is_err());
+    }
 }
@@ -1,132 +1,319 @@
 use crate::prelude::*;
 
-use std::collections::{HashSet, VecDeque};
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap, HashSet, VecDeque},
+    hash::{Hash, Hasher},
+    io,
+};
 
-pub enum Day22 {}
+pub type Deck = VecDeque<usize>;
+
+pub fn score(deck: &Deck) -> usize {
+    deck.iter()
+        .rev()
+        .enumerate()
+        .map(|(i, v)| (i + 1) * v)
+        .sum()
+}
 
-#[derive(PartialEq)]
-enum Winner {
-    P1,
-    P2,
+#[derive(Debug, Clone)]
+struct Hand {
+    player: usize,
+    cards: Deck,
+}
+
+fn state_hash(hands: &[Hand]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    for hand in hands {
+        hand.cards.hash(&mut hasher);
+    }
+    hasher.finish()
 }
 
-fn play_game(p1: &mut VecDeque<usize>, p2: &mut VecDeque<usize>) -> Winner {
-    while !(p1.is_empty() || p2.is_empty()) {
-        let p1_card = p1.pop_front().unwrap();
-        let p2_card = p2.pop_front().unwrap();
-        if p1_card > p2_card {
-            p1.push_back(p1_card);
-            p1.push_back(p2_card);
-        } else if p2_card > p1_card {
-            p2.push_back(p2_card);
-            p2.push_back(p1_card);
-        } else {
-            panic!("wat");
+/// Every hand's length followed by its cards, for comparing states whose hashes collide.
+fn flatten(hands: &[Hand]) -> Box<[usize]> {
+    hands
+        .iter()
+        .flat_map(|h| std::iter::once(h.cards.len()).chain(h.cards.iter().copied()))
+        .collect()
+}
+
+/// Flattened starting decks of a sub-game and the position of its winner.
+type CacheEntry = (Box<[usize]>, usize);
+
+/// The winner of a round and the card each active player put down.
+type Round = (usize, Vec<(usize, usize)>);
+
+/// Undoes `rounds`, oldest first, to recover the decks from before the first of them.
+fn rewind(hands: &[Hand], rounds: &[Round]) -> Vec<Deck> {
+    let mut decks = hands.iter().map(|h| h.cards.clone()).collect_vec();
+    for (winner, plays) in rounds.iter().rev() {
+        let kept = decks[*winner].len() - plays.len();
+        decks[*winner].truncate(kept);
+        for &(i, card) in plays {
+            decks[i].push_front(card);
         }
     }
-    if p1.is_empty() {
-        Winner::P2
-    } else {
-        Winner::P1
+    decks
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Outcome {
+    /// Index of the winning player.
+    pub winner: usize,
+    pub decks: Vec<Deck>,
+}
+
+impl Outcome {
+    pub fn score(&self) -> usize {
+        score(&self.decks[self.winner])
     }
 }
 
-fn play_rec_game(p1: &mut VecDeque<usize>, p2: &mut VecDeque<usize>) -> Winner {
-    let mut states = HashSet::<(Vec<usize>, Vec<usize>)>::new();
+/// Plays Combat between any number of players. Every round, each player with cards left plays
+/// their top card and the highest card wins; the winner takes their own card first, then the
+/// others from highest to lowest.
+///
+/// In the recursive variant a repeated position ends the game in favour of the first player still
+/// holding cards, and if every player has at least as many cards left as the value they played,
+/// the round is decided by a sub-game played with that many of their top cards.
+pub struct Combat<'a> {
+    recursive: bool,
+    /// Sub-game winners by the hash of the starting decks.
+    cache: HashMap<u64, Vec<CacheEntry>>,
+    games: usize,
+    log: Option<&'a mut dyn io::Write>,
+}
+
+impl<'a> Combat<'a> {
+    pub fn new(recursive: bool) -> Self {
+        Self {
+            recursive,
+            cache: HashMap::new(),
+            games: 0,
+            log: None,
+        }
+    }
 
-    while !(p1.is_empty() || p2.is_empty()) {
-        let state = (p1.iter().copied().collect(), p2.iter().copied().collect());
-        if !states.insert(state) {
-            return Winner::P1;
+    /// Writes a round-by-round log in the puzzle's format. Sub-game results aren't cached while
+    /// logging, so that every game shows up in the log.
+    pub fn with_log(mut self, log: &'a mut dyn io::Write) -> Self {
+        self.log = Some(log);
+        self
+    }
+
+    pub fn play(&mut self, decks: Vec<Deck>) -> Result<Outcome, Error> {
+        if decks.len() < 2 {
+            return Err("need at least two players".into());
+        }
+        let mut seen = HashSet::new();
+        if !decks.iter().flatten().all(|&card| seen.insert(card)) {
+            return Err("every card must be unique".into());
         }
 
-        let p1_card = p1.pop_front().unwrap();
-        let p2_card = p2.pop_front().unwrap();
-
-        let round_winner = if p1_card <= p1.len() && p2_card <= p2.len() {
-            p1.make_contiguous();
-            p2.make_contiguous();
-            let mut p1_subdeck = p1.as_slices().0[..p1_card].to_vec().into();
-            let mut p2_subdeck = p2.as_slices().0[..p2_card].to_vec().into();
-            play_rec_game(&mut p1_subdeck, &mut p2_subdeck)
-        } else {
-            if p1_card > p2_card {
-                Winner::P1
-            } else if p2_card > p1_card {
-                Winner::P2
+        self.games = 0;
+        let hands = decks
+            .into_iter()
+            .enumerate()
+            .map(|(player, cards)| Hand { player, cards })
+            .collect();
+        let (winner, hands) = self.game(hands)?;
+
+        if let Some(log) = &mut self.log {
+            writeln!(log, "== Post-game results ==")?;
+            for hand in &hands {
+                writeln!(
+                    log,
+                    "Player {}'s deck: {}",
+                    hand.player + 1,
+                    hand.cards.iter().join(", ")
+                )?;
+            }
+        }
+
+        Ok(Outcome {
+            winner,
+            decks: hands.into_iter().map(|h| h.cards).collect(),
+        })
+    }
+
+    /// Returns the position of the winner in `hands` along with the final hands.
+    fn game(&mut self, mut hands: Vec<Hand>) -> Result<(usize, Vec<Hand>), Error> {
+        self.games += 1;
+        let game = self.games;
+        // positions are stored as the number of rounds played before them, and recovered by
+        // rewinding from the current position when their hash comes up again
+        let mut seen: HashMap<u64, Vec<usize>> = HashMap::new();
+        let mut history: Vec<Round> = Vec::new();
+
+        if self.recursive {
+            if let Some(log) = &mut self.log {
+                writeln!(log, "=== Game {} ===\n", game)?;
+            }
+        }
+
+        let mut round = 0;
+        let winner = loop {
+            let active = (0..hands.len())
+                .filter(|&i| !hands[i].cards.is_empty())
+                .collect_vec();
+            match active.as_slice() {
+                [] => return Err("no cards to play with".into()),
+                [winner] => break *winner,
+                _ => (),
+            }
+            let hash = state_hash(&hands);
+            let earlier = seen.entry(hash).or_default();
+            let repeated = earlier.iter().any(|&r| {
+                let decks = rewind(&hands, &history[r..]);
+                decks.iter().eq(hands.iter().map(|h| &h.cards))
+            });
+            if repeated {
+                if self.recursive {
+                    break active[0];
+                }
+                return Err("game never ends".into());
+            }
+            earlier.push(history.len());
+            round += 1;
+
+            if let Some(log) = &mut self.log {
+                if self.recursive {
+                    writeln!(log, "-- Round {} (Game {}) --", round, game)?;
+                } else {
+                    writeln!(log, "-- Round {} --", round)?;
+                }
+                for hand in &hands {
+                    writeln!(
+                        log,
+                        "Player {}'s deck: {}",
+                        hand.player + 1,
+                        hand.cards.iter().join(", ")
+                    )?;
+                }
+            }
+
+            let plays = active
+                .iter()
+                .map(|&i| (i, hands[i].cards.pop_front().unwrap()))
+                .collect_vec();
+            if let Some(log) = &mut self.log {
+                for &(i, card) in &plays {
+                    writeln!(log, "Player {} plays: {}", hands[i].player + 1, card)?;
+                }
+            }
+
+            let recurse =
+                self.recursive && plays.iter().all(|&(i, card)| hands[i].cards.len() >= card);
+            let round_winner = if recurse {
+                let sub_hands = plays
+                    .iter()
+                    .map(|&(i, card)| Hand {
+                        player: hands[i].player,
+                        cards: hands[i].cards.iter().take(card).copied().collect(),
+                    })
+                    .collect();
+                plays[self.sub_game(sub_hands, game)?].0
             } else {
-                panic!("wat");
+                plays.iter().max_by_key(|&&(_, card)| card).unwrap().0
+            };
+
+            if let Some(log) = &mut self.log {
+                let player = hands[round_winner].player + 1;
+                if self.recursive {
+                    writeln!(
+                        log,
+                        "Player {} wins round {} of game {}!\n",
+                        player, round, game
+                    )?;
+                } else {
+                    writeln!(log, "Player {} wins the round!\n", player)?;
+                }
             }
+
+            let winning_card = plays.iter().find(|&&(i, _)| i == round_winner).unwrap().1;
+            let others = plays
+                .iter()
+                .map(|&(_, card)| card)
+                .filter(|&card| card != winning_card)
+                .sorted()
+                .rev();
+            let cards = &mut hands[round_winner].cards;
+            cards.push_back(winning_card);
+            cards.extend(others);
+            history.push((round_winner, plays));
         };
 
-        match round_winner {
-            Winner::P1 => {
-                p1.push_back(p1_card);
-                p1.push_back(p2_card);
-            }
-            Winner::P2 => {
-                p2.push_back(p2_card);
-                p2.push_back(p1_card);
+        if self.recursive {
+            if let Some(log) = &mut self.log {
+                writeln!(
+                    log,
+                    "The winner of game {} is player {}!\n",
+                    game,
+                    hands[winner].player + 1
+                )?;
             }
         }
+        Ok((winner, hands))
     }
-    if p1.is_empty() {
-        Winner::P2
-    } else {
-        Winner::P1
+
+    fn sub_game(&mut self, hands: Vec<Hand>, parent: usize) -> Result<usize, Error> {
+        if self.log.is_none() {
+            let hash = state_hash(&hands);
+            let key = flatten(&hands);
+            let mut cached = self.cache.get(&hash).into_iter().flatten();
+            if let Some((_, winner)) = cached.find(|(k, _)| *k == key) {
+                return Ok(*winner);
+            }
+            let (winner, _) = self.game(hands)?;
+            self.cache.entry(hash).or_default().push((key, winner));
+            return Ok(winner);
+        }
+
+        if let Some(log) = &mut self.log {
+            writeln!(log, "Playing a sub-game to determine the winner...\n")?;
+        }
+        let (winner, _) = self.game(hands)?;
+        if let Some(log) = &mut self.log {
+            writeln!(log, "...anyway, back to game {}.", parent)?;
+        }
+        Ok(winner)
     }
 }
 
+pub enum Day22 {}
+
 impl Challenge for Day22 {
-    type Input = (VecDeque<usize>, VecDeque<usize>);
+    type Input = Vec<Deck>;
     type Output1 = usize;
     type Output2 = usize;
 
     fn read(data: File) -> Result<Self::Input, Error> {
-        let mut lines = data.lines();
-        lines.next();
-        let mut p1 = VecDeque::new();
-        for line in &mut lines {
+        let mut decks = Vec::new();
+        for line in data.lines() {
             let line = line?;
-            if line == "" {
-                break;
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            if line.starts_with("Player ") && line.ends_with(':') {
+                decks.push(Deck::new());
+            } else {
+                decks
+                    .last_mut()
+                    .ok_or("card before player header")?
+                    .push_back(line.parse()?);
             }
-            p1.push_back(line.parse()?);
-        }
-        lines.next();
-        let mut p2 = VecDeque::new();
-        for line in lines {
-            p2.push_back(line?.parse()?);
         }
-        Ok((p1, p2))
+        Ok(decks)
     }
 
-    fn part1((mut p1, mut p2): Self::Input) -> Self::Output1 {
-        let winner = match play_game(&mut p1, &mut p2) {
-            Winner::P1 => p1,
-            Winner::P2 => p2,
-        };
-
-        winner
-            .into_iter()
-            .rev()
-            .enumerate()
-            .map(|(i, v)| (i + 1) * v)
-            .sum()
+    fn part1(input: Self::Input) -> Self::Output1 {
+        Combat::new(false).play(input).unwrap().score()
     }
 
-    fn part2((mut p1, mut p2): Self::Input) -> Self::Output2 {
-        let winner = match play_rec_game(&mut p1, &mut p2) {
-            Winner::P1 => p1,
-            Winner::P2 => p2,
-        };
-
-        winner
-            .into_iter()
-            .rev()
-            .enumerate()
-            .map(|(i, v)| (i + 1) * v)
-            .sum()
+    fn part2(input: Self::Input) -> Self::Output2 {
+        Combat::new(true).play(input).unwrap().score()
     }
 }
 
@@ -135,7 +322,7 @@ mod test {
     use super::*;
 
     fn sample_input() -> <Day22 as Challenge>::Input {
-        (vec![9, 2, 6, 3, 1].into(), vec![5, 8, 4, 7, 10].into())
+        vec![vec![9, 2, 6, 3, 1].into(), vec![5, 8, 4, 7, 10].into()]
     }
 
     #[test]
@@ -147,4 +334,110 @@ mod test {
     fn test_day22_part2() {
         assert_eq!(Day22::part2(sample_input()), 291);
     }
+
+    #[test]
+    fn test_day22_log() {
+        let mut log = Vec::new();
+        let outcome = Combat::new(true)
+            .with_log(&mut log)
+            .play(sample_input())
+            .unwrap();
+        assert_eq!(outcome.winner, 1);
+        assert_eq!(outcome.score(), 291);
+
+        let log = String::from_utf8(log).unwrap();
+        assert!(log.starts_with(
+            "=== Game 1 ===\n\n\
+             -- Round 1 (Game 1) --\n\
+             Player 1's deck: 9, 2, 6, 3, 1\n\
+             Player 2's deck: 5, 8, 4, 7, 10\n\
+             Player 1 plays: 9\n\
+             Player 2 plays: 5\n\
+             Player 1 wins round 1 of game 1!\n\n"
+        ));
+        assert!(log.contains(
+            "-- Round 9 (Game 1) --\n\
+             Player 1's deck: 4, 9, 8, 5, 2\n\
+             Player 2's deck: 3, 10, 1, 7, 6\n\
+             Player 1 plays: 4\n\
+             Player 2 plays: 3\n\
+             Playing a sub-game to determine the winner...\n\n\
+             === Game 2 ===\n\n\
+             -- Round 1 (Game 2) --\n\
+             Player 1's deck: 9, 8, 5, 2\n\
+             Player 2's deck: 10, 1, 7\n"
+        ));
+        assert!(log.contains(
+            "The winner of game 2 is player 2!\n\n\
+             ...anyway, back to game 1.\n\
+             Player 2 wins round 9 of game 1!\n\n"
+        ));
+        assert!(log.ends_with(
+            "The winner of game 1 is player 2!\n\n\
+             == Post-game results ==\n\
+             Player 1's deck: \n\
+             Player 2's deck: 7, 5, 6, 2, 4, 1, 10, 8, 9, 3\n"
+        ));
+
+        let mut log = Vec::new();
+        Combat::new(false)
+            .with_log(&mut log)
+            .play(sample_input())
+            .unwrap();
+        let log = String::from_utf8(log).unwrap();
+        assert!(log.contains("-- Round 29 --\n"));
+        assert!(log.contains("Player 1 wins the round!\n\n-- Round 2 --\n"));
+        assert!(log.ends_with("Player 2's deck: 3, 2, 10, 6, 8, 5, 9, 4, 7, 1\n"));
+    }
+
+    #[test]
+    fn test_day22_rewind() {
+        let hands = [vec![2, 6, 3, 1, 9, 5], vec![8, 4, 7, 10]]
+            .iter()
+            .enumerate()
+            .map(|(player, cards)| Hand {
+                player,
+                cards: cards.iter().copied().collect(),
+            })
+            .collect_vec();
+        let decks = rewind(&hands, &[(0, vec![(0, 9), (1, 5)])]);
+        assert_eq!(decks, sample_input());
+        assert_eq!(
+            rewind(&hands, &[]),
+            [hands[0].cards.clone(), hands[1].cards.clone()]
+        );
+        assert_ne!(flatten(&hands), flatten(&hands[1..]));
+    }
+
+    #[test]
+    fn test_day22_infinite() {
+        let decks = vec![vec![43, 19].into(), vec![2, 29, 14].into()];
+        assert_eq!(Combat::new(true).play(decks).unwrap().winner, 0);
+    }
+
+    #[test]
+    fn test_day22_more_players() {
+        let decks = vec![vec![3, 8].into(), vec![5, 1].into(), vec![7, 2].into()];
+        let outcome = Combat::new(false).play(decks).unwrap();
+        assert_eq!(outcome.winner, 0);
+        assert_eq!(outcome.decks[0], [7, 2, 8, 3, 5, 1]);
+
+        let decks = vec![
+            vec![9, 2, 6].into(),
+            vec![5, 8, 4].into(),
+            vec![1, 7, 3, 10, 11].into(),
+        ];
+        let outcome = Combat::new(true).play(decks).unwrap();
+        assert_eq!(outcome.decks.iter().map(|d| d.len()).sum::<usize>(), 11);
+        assert_eq!(outcome.decks[outcome.winner].len(), 11);
+    }
+
+    #[test]
+    fn test_day22_invalid() {
+        assert!(Combat::new(true).play(vec![vec![1, 2].into()]).is_err());
+        let tied = vec![vec![1, 2].into(), vec![2, 3].into()];
+        assert!(Combat::new(false).play(tied).is_err());
+        let empty = vec![Deck::new(), Deck::new()];
+        assert!(Combat::new(false).play(empty).is_err());
+    }
 }
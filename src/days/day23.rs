@@ -106,92 +106,206 @@ fn do_move(n: &mut Node<u32>) {
     referent.borrow_mut().insert_after(a);
 }
 
-pub enum Day23 {}
+/// The original linked-list implementation of part 1, kept as a reference.
+pub fn linked_part1(input: Vec<u32>) -> Vec<u32> {
+    let mut n = Node::from_iter(input);
+    establish_referents(&n, 9);
 
-impl Challenge for Day23 {
-    type Input = Vec<u32>;
-    type Output1 = Vec<u32>;
-    type Output2 = u64;
+    for _ in 0..100 {
+        do_move(&mut n.borrow_mut());
+        next!(n);
+    }
 
-    fn read(data: File) -> Result<Self::Input, Error> {
-        Ok(data
-            .bytes()
-            .map(Result::unwrap)
-            .filter(u8::is_ascii_digit)
-            .map(|n| (n - b'0') as u32)
-            .collect())
+    while n.borrow().val != 1 {
+        next!(n);
     }
+    next!(n);
 
-    fn part1(input: Self::Input) -> Self::Output1 {
-        let mut n = Node::from_iter(input);
-        establish_referents(&n, 9);
+    let mut v = Vec::new();
+    while n.borrow().val != 1 {
+        v.push(n.borrow().val);
+        next!(n);
+    }
+    v
+}
 
-        for _ in 0..100 {
-            do_move(&mut n.borrow_mut());
-            next!(n);
-        }
+/// The original linked-list implementation of part 2, kept as a reference.
+pub fn linked_part2(mut input: Vec<u32>) -> u64 {
+    // Establish the initial 9 nodes, plus a guaranteed 10 at the end.
+    input.push(10);
+    let mut n = Node::from_iter(input);
+    establish_referents(&n, 10);
+
+    // Get a reference to the currently final node. Whatever.
+    let mut tail = Rc::clone(&n);
+    for _ in 0..9 {
+        next!(tail); // advance to the actual tail
+    }
+    assert_eq!(tail.borrow().val, 10);
+
+    // Add the remaining 999990 nodes.
+    // Doing it like this prevents O(n) time complexity for establishing each referent,
+    // since with a singly linked list you'd have to wrap around and all that.
+    // If we know it to be sorted, then whatever.
+    for i in 11..=1_000_000 {
+        let new_node = Rc::new(RefCell::new(Node {
+            val: i,
+            next: Some(Rc::clone(&n)),
+            referent: Some(Rc::clone(&tail)),
+        }));
+        tail.borrow_mut().set_next(&new_node);
+        tail = new_node;
+    }
 
-        while n.borrow().val != 1 {
-            next!(n);
-        }
+    // Set up the n=1 node to point back at the new final node.
+    let mut one = Rc::clone(&n);
+    while one.borrow().val != 1 {
+        next!(one);
+    }
+    one.borrow_mut().referent = Some(tail);
+
+    // Do the thing!
+    for _ in 0..10_000_000 {
+        do_move(&mut n.borrow_mut());
         next!(n);
+    }
 
-        let mut v = Vec::new();
-        while n.borrow().val != 1 {
-            v.push(n.borrow().val);
-            next!(n);
-        }
-        v
+    while n.borrow().val != 1 {
+        next!(n);
     }
+    next!(n);
+    let a = n.borrow().val;
+    next!(n);
+    let b = n.borrow().val;
+    a as u64 * b as u64
+}
 
-    fn part2(mut input: Self::Input) -> Self::Output2 {
-        // Establish the initial 9 nodes, plus a guaranteed 10 at the end.
-        input.push(10);
-        let mut n = Node::from_iter(input);
-        establish_referents(&n, 10);
+/// A circle of cups labelled `1..=n`, stored as the label of the cup clockwise of each cup.
+pub struct Cups {
+    next: Vec<u32>,
+    current: u32,
+    pick: usize,
+}
 
-        // Get a reference to the currently final node. Whatever.
-        let mut tail = Rc::clone(&n);
-        for _ in 0..9 {
-            next!(tail); // advance to the actual tail
+impl Cups {
+    /// Starts with `labels` in order, followed by the remaining cups up to `total`. Each move
+    /// picks up `pick` cups.
+    pub fn new(labels: &[u32], total: u32, pick: usize) -> Result<Self, Error> {
+        if labels.is_empty() {
+            return Err("no cups".into());
         }
-        assert_eq!(tail.borrow().val, 10);
-
-        // Add the remaining 999990 nodes.
-        // Doing it like this prevents O(n) time complexity for establishing each referent,
-        // since with a singly linked list you'd have to wrap around and all that.
-        // If we know it to be sorted, then whatever.
-        for i in 11..=1_000_000 {
-            let new_node = Rc::new(RefCell::new(Node {
-                val: i,
-                next: Some(Rc::clone(&n)),
-                referent: Some(Rc::clone(&tail)),
-            }));
-            tail.borrow_mut().set_next(&new_node);
-            tail = new_node;
+        let n = labels.len();
+        if labels.iter().copied().sorted().ne(1..=n as u32) {
+            return Err("starting cups must be labelled 1 to n".into());
+        }
+        if (total as usize) < n {
+            return Err("fewer cups than starting labels".into());
+        }
+        if pick + 2 > total as usize {
+            return Err("too many cups picked per move".into());
         }
 
-        // Set up the n=1 node to point back at the new final node.
-        let mut one = Rc::clone(&n);
-        while one.borrow().val != 1 {
-            next!(one);
+        let order = labels.iter().copied().chain(n as u32 + 1..=total);
+        let mut next = vec![0; total as usize + 1];
+        for (a, b) in order.clone().zip(order.clone().cycle().skip(1)) {
+            next[a as usize] = b;
         }
-        one.borrow_mut().referent = Some(tail);
 
-        // Do the thing!
-        for _ in 0..10_000_000 {
-            do_move(&mut n.borrow_mut());
-            next!(n);
+        Ok(Self {
+            next,
+            current: labels[0],
+            pick,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.next.len() - 1
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn current(&self) -> u32 {
+        self.current
+    }
+
+    pub fn step(&mut self) {
+        let mut picked = Vec::with_capacity(self.pick);
+        let mut last = self.current;
+        for _ in 0..self.pick {
+            last = self.next[last as usize];
+            picked.push(last);
         }
 
-        while n.borrow().val != 1 {
-            next!(n);
+        let total = self.len() as u32;
+        let mut destination = self.current;
+        loop {
+            destination = if destination == 1 {
+                total
+            } else {
+                destination - 1
+            };
+            if !picked.contains(&destination) {
+                break;
+            }
         }
-        next!(n);
-        let a = n.borrow().val;
-        next!(n);
-        let b = n.borrow().val;
-        a as u64 * b as u64
+
+        if let Some(&first) = picked.first() {
+            self.next[self.current as usize] = self.next[last as usize];
+            self.next[last as usize] = self.next[destination as usize];
+            self.next[destination as usize] = first;
+        }
+        self.current = self.next[self.current as usize];
+    }
+
+    pub fn run(&mut self, moves: usize) -> &mut Self {
+        (0..moves).for_each(|_| self.step());
+        self
+    }
+
+    /// The labels clockwise of `label`, not including it.
+    pub fn after(&self, label: u32) -> impl Iterator<Item = u32> + '_ {
+        let mut cup = label;
+        (1..self.len()).map(move |_| {
+            cup = self.next[cup as usize];
+            cup
+        })
+    }
+}
+
+pub enum Day23 {}
+
+impl Challenge for Day23 {
+    type Input = Vec<u32>;
+    type Output1 = String;
+    type Output2 = u64;
+
+    fn read(data: File) -> Result<Self::Input, Error> {
+        Ok(data
+            .bytes()
+            .map(Result::unwrap)
+            .filter(u8::is_ascii_digit)
+            .map(|n| (n - b'0') as u32)
+            .collect())
+    }
+
+    fn part1(input: Self::Input) -> Self::Output1 {
+        Cups::new(&input, input.len() as u32, 3)
+            .unwrap()
+            .run(100)
+            .after(1)
+            .join("")
+    }
+
+    fn part2(input: Self::Input) -> Self::Output2 {
+        Cups::new(&input, 1_000_000, 3)
+            .unwrap()
+            .run(10_000_000)
+            .after(1)
+            .take(2)
+            .map(u64::from)
+            .product()
     }
 }
 
@@ -205,11 +319,47 @@ mod test {
 
     #[test]
     fn test_day23_part1() {
-        assert_eq!(Day23::part1(sample_input()), vec![6, 7, 3, 8, 4, 5, 2, 9]);
+        assert_eq!(Day23::part1(sample_input()), "67384529");
     }
 
     #[test]
     fn test_day23_part2() {
         assert_eq!(Day23::part2(sample_input()), 149245887792);
     }
+
+    #[test]
+    fn test_day23_linked_reference() {
+        let inputs = [
+            sample_input(),
+            vec![1, 2, 3, 4, 5, 6, 7, 8, 9],
+            vec![9, 8, 7, 6, 5, 4, 3, 2, 1],
+            vec![5, 3, 8, 9, 1, 4, 7, 6, 2],
+        ];
+        for input in inputs.iter() {
+            assert_eq!(
+                Day23::part1(input.clone()),
+                linked_part1(input.clone()).iter().join("")
+            );
+        }
+        assert_eq!(Day23::part2(sample_input()), linked_part2(sample_input()));
+    }
+
+    #[test]
+    fn test_day23_configurable() {
+        let mut cups = Cups::new(&sample_input(), 9, 3).unwrap();
+        cups.run(10);
+        assert_eq!(cups.after(1).join(""), "92658374");
+        assert_eq!(cups.current(), 8);
+
+        // the destination wraps around to the highest label
+        let mut cups = Cups::new(&[1, 2, 3], 5, 1).unwrap();
+        assert_eq!(cups.run(1).after(1).collect_vec(), [3, 4, 5, 2]);
+        let mut cups = Cups::new(&[3, 1, 2], 3, 0).unwrap();
+        assert_eq!(cups.run(5).after(3).collect_vec(), [1, 2]);
+
+        assert!(Cups::new(&[], 5, 3).is_err());
+        assert!(Cups::new(&[1, 3], 5, 3).is_err());
+        assert!(Cups::new(&[1, 2, 3], 2, 1).is_err());
+        assert!(Cups::new(&[1, 2, 3], 4, 3).is_err());
+    }
 }
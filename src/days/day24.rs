@@ -1,97 +1,58 @@
-use crate::prelude::*;
-
-use std::collections::HashMap;
-
-#[derive(Debug, Copy, Clone)]
-pub enum Direction {
-    East,
-    Southeast,
-    Southwest,
-    West,
-    Northwest,
-    Northeast,
-}
-
-fn take_byte(b: &mut &[u8]) -> u8 {
-    let v = b[0];
-    *b = &b[1..];
-    v
-}
+use crate::{
+    hex::{Axial, Direction, Layout},
+    prelude::*,
+};
 
-impl Direction {
-    fn from_bytes(mut b: &[u8]) -> Vec<Self> {
-        let mut v = Vec::new();
-        while !b.is_empty() {
-            let dir = match take_byte(&mut b) {
-                b'e' => Self::East,
-                b'w' => Self::West,
-                c => match &[c, take_byte(&mut b)] {
-                    b"se" => Self::Southeast,
-                    b"sw" => Self::Southwest,
-                    b"nw" => Self::Northwest,
-                    b"ne" => Self::Northeast,
-                    _ => panic!("wat"),
-                },
-            };
-            v.push(dir);
-        }
-        v
-    }
-
-    fn to_offset(self) -> (i32, i32) {
-        match self {
-            Self::East => (2, 0),
-            Self::Southeast => (1, 2),
-            Self::Southwest => (-1, 2),
-            Self::West => (-2, 0),
-            Self::Northwest => (-1, -2),
-            Self::Northeast => (1, -2),
-        }
-    }
-}
+use std::collections::{HashMap, HashSet};
 
-fn flipped_tiles(input: <Day24 as Challenge>::Input) -> HashMap<(i32, i32), bool> {
-    let mut tiles = HashMap::<_, bool>::new();
+fn flipped_tiles(input: &<Day24 as Challenge>::Input) -> HashSet<Axial> {
+    let mut black = HashSet::new();
     for identifier in input {
-        let (mut x, mut y) = (0, 0);
-        for dir in identifier {
-            let (dx, dy) = dir.to_offset();
-            x += dx;
-            y += dy;
+        let tile = identifier
+            .iter()
+            .fold(Axial::ORIGIN, |tile, &dir| tile.neighbor(dir));
+        if !black.remove(&tile) {
+            black.insert(tile);
         }
-        let entry = tiles.entry((x, y)).or_default();
-        *entry = !*entry;
     }
-    tiles
+    black
 }
 
-fn next_gen(prev: &HashMap<(i32, i32), bool>) -> HashMap<(i32, i32), bool> {
-    let mut neighbor_counts = prev
-        .keys()
-        .copied()
-        .map(|k| (k, 0))
-        .collect::<HashMap<_, _>>();
-
-    for (&pos, &black) in prev.iter() {
-        if black {
-            for dir in Direction::from_bytes(b"eseswwnwne") {
-                let (dx, dy) = dir.to_offset();
-                let pos2 = (pos.0 + dx, pos.1 + dy);
-                let n = neighbor_counts.entry(pos2).or_default();
-                *n += 1;
-            }
+fn next_gen(black: &HashSet<Axial>) -> HashSet<Axial> {
+    let mut neighbor_counts = HashMap::<_, usize>::new();
+    for tile in black {
+        for neighbor in tile.neighbors() {
+            *neighbor_counts.entry(neighbor).or_default() += 1;
         }
     }
 
     neighbor_counts
         .into_iter()
-        .map(|(pos, n)| {
-            let is_black = n == 2 || (n == 1 && prev.get(&pos) == Some(&true));
-            (pos, is_black)
-        })
+        .filter(|&(tile, n)| n == 2 || (n == 1 && black.contains(&tile)))
+        .map(|(tile, _)| tile)
         .collect()
 }
 
+/// The lobby floor after `days` days, drawn with black tiles over the white ones next to them.
+pub fn floor_svg(input: &<Day24 as Challenge>::Input, days: usize) -> String {
+    let mut black = flipped_tiles(input);
+    for _ in 0..days {
+        black = next_gen(&black);
+    }
+
+    let white: HashSet<_> = black
+        .iter()
+        .flat_map(|t| t.neighbors())
+        .filter(|t| !black.contains(t))
+        .collect();
+    let tiles = black
+        .iter()
+        .sorted()
+        .map(|&t| (t, "black"))
+        .chain(white.into_iter().sorted().map(|t| (t, "white")));
+    Layout::PointyTop.to_svg(tiles, 10.0)
+}
+
 pub enum Day24 {}
 
 impl Challenge for Day24 {
@@ -100,23 +61,21 @@ impl Challenge for Day24 {
     type Output2 = usize;
 
     fn read(data: File) -> Result<Self::Input, Error> {
-        let mut v = Vec::new();
-        for line in data.lines() {
-            v.push(Direction::from_bytes(line?.as_bytes()));
-        }
-        Ok(v)
+        data.lines()
+            .map(|line| Layout::PointyTop.parse_path(&line?))
+            .collect()
     }
 
     fn part1(input: Self::Input) -> Self::Output1 {
-        flipped_tiles(input).values().filter(|x| **x).count()
+        flipped_tiles(&input).len()
     }
 
     fn part2(input: Self::Input) -> Self::Output2 {
-        let mut tiles = flipped_tiles(input);
+        let mut tiles = flipped_tiles(&input);
         for _ in 0..100 {
             tiles = next_gen(&tiles);
         }
-        tiles.values().filter(|x| **x).count()
+        tiles.len()
     }
 }
 
@@ -147,9 +106,9 @@ mod test {
             "neswnwewnwnwseenwseesewsenwsweewe",
             "wseweeenwnesenwwwswnew",
         ]
-        .map(str::as_bytes)
-        .map(Direction::from_bytes)
-        .to_vec()
+        .iter()
+        .map(|s| Layout::PointyTop.parse_path(s).unwrap())
+        .collect()
     }
 
     #[test]
@@ -161,4 +120,11 @@ mod test {
     fn test_day24_part2() {
         assert_eq!(Day24::part2(sample_input()), 2208);
     }
+
+    #[test]
+    fn test_day24_svg() {
+        let svg = floor_svg(&sample_input(), 0);
+        assert_eq!(svg.matches("fill=\"black\"").count(), 10);
+        assert!(svg.contains("fill=\"white\""));
+    }
 }
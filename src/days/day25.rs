@@ -1,53 +1,40 @@
-use crate::prelude::*;
+use crate::{
+    number_theory::{discrete_log, pow_mod},
+    prelude::*,
+};
 
-fn transform_subj_num(subj: u64, loops: u64) -> u64 {
-    let mut n = 1;
-    for _ in 0..loops {
-        n *= subj;
-        n %= 20201227;
-    }
-    n
-}
-
-fn derive_loop_size(subj: u64, pubkey: u64) -> u64 {
-    let mut n = 1;
-    let mut i = 0;
-    while n != pubkey {
-        n *= subj;
-        n %= 20201227;
-        i += 1;
-    }
-    i
+fn derive_loop_size(subj: u64, pubkey: u64, modulus: u64) -> Result<u64, Error> {
+    Ok(discrete_log(subj.into(), pubkey.into(), modulus.into())? as u64)
 }
 
-fn handshake(card_loops: u64, door_loops: u64) -> u64 {
-    let card_pubkey = transform_subj_num(7, card_loops);
-    let door_pubkey = transform_subj_num(7, door_loops);
-    let key_a = transform_subj_num(card_pubkey, door_loops);
-    let key_b = transform_subj_num(door_pubkey, card_loops);
-    assert_eq!(key_a, key_b);
-    key_a
+fn encryption_key(
+    (card_pubkey, door_pubkey): (u64, u64),
+    subj: u64,
+    modulus: u64,
+) -> Result<u64, Error> {
+    let card_loops = derive_loop_size(subj, card_pubkey, modulus)?;
+    let key = pow_mod(door_pubkey.into(), card_loops.into(), modulus.into())
+        .ok_or("modulus must be positive")?;
+    Ok(key as u64)
 }
 
-pub enum Day25 {}
+pub enum Day25<const MODULUS: u64 = 20201227, const SUBJECT: u64 = 7> {}
 
-impl Challenge for Day25 {
+impl<const MODULUS: u64, const SUBJECT: u64> Challenge for Day25<MODULUS, SUBJECT> {
     type Input = (u64, u64);
     type Output1 = u64;
     type Output2 = ();
 
     fn read(data: File) -> Result<Self::Input, Error> {
-        Ok(data
-            .lines()
-            .map(|s| s.unwrap().parse().unwrap())
+        let keys: Vec<u64> = data.parse_lines()?;
+        Ok(keys
+            .into_iter()
             .collect_tuple()
-            .unwrap())
+            .ok_or("expected two public keys")?)
     }
 
-    fn part1((card_pubkey, door_pubkey): Self::Input) -> Self::Output1 {
-        let card_loops = derive_loop_size(7, card_pubkey);
-        let door_loops = derive_loop_size(7, door_pubkey);
-        handshake(card_loops, door_loops)
+    fn part1(input: Self::Input) -> Self::Output1 {
+        encryption_key(input, SUBJECT, MODULUS).unwrap()
     }
 
     fn part2(_: Self::Input) -> Self::Output2 {}
@@ -63,12 +50,20 @@ mod test {
 
     #[test]
     fn test_loopsize_derivation() {
-        assert_eq!(derive_loop_size(7, 5764801), 8);
-        assert_eq!(derive_loop_size(7, 17807724), 11);
+        assert_eq!(derive_loop_size(7, 5764801, 20201227).unwrap(), 8);
+        assert_eq!(derive_loop_size(7, 17807724, 20201227).unwrap(), 11);
     }
 
     #[test]
     fn test_day25_part1() {
-        assert_eq!(Day25::part1(sample_input()), 14897079);
+        assert_eq!(<Day25>::part1(sample_input()), 14897079);
+    }
+
+    #[test]
+    fn test_day25_parameters() {
+        // with 3 as the subject number mod 17, the keys 13 and 15 have loop sizes 4 and 6
+        assert_eq!(Day25::<17, 3>::part1((13, 15)), 16);
+        assert_eq!(encryption_key((15, 13), 3, 17).unwrap(), 16);
+        assert!(encryption_key((3, 5), 2, 7).is_err());
     }
 }
@@ -0,0 +1,374 @@
+use crate::prelude::*;
+
+use std::{collections::HashSet, fmt, rc::Rc};
+
+pub mod analysis;
+
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct Cpu {
+    pub ip: i32,
+    pub acc: i32,
+}
+
+pub trait Opcode {
+    fn mnemonic(&self) -> &'static str;
+
+    /// Applies the instruction to `cpu` and returns the offset to add to `ip`, or `None` if it faults.
+    fn execute(&self, arg: i32, cpu: &mut Cpu) -> Option<i32>;
+}
+
+#[derive(Clone)]
+pub enum Operation {
+    Acc,
+    Jmp,
+    Nop,
+    Custom(Rc<dyn Opcode>),
+}
+
+impl Opcode for Operation {
+    fn mnemonic(&self) -> &'static str {
+        match self {
+            Self::Acc => "acc",
+            Self::Jmp => "jmp",
+            Self::Nop => "nop",
+            Self::Custom(op) => op.mnemonic(),
+        }
+    }
+
+    fn execute(&self, arg: i32, cpu: &mut Cpu) -> Option<i32> {
+        match self {
+            Self::Acc => {
+                cpu.acc = cpu.acc.checked_add(arg)?;
+                Some(1)
+            }
+            Self::Jmp => Some(arg),
+            Self::Nop => Some(1),
+            Self::Custom(op) => op.execute(arg, cpu),
+        }
+    }
+}
+
+impl fmt::Debug for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.mnemonic())
+    }
+}
+
+/// Custom opcodes are only equal to themselves, not to other opcodes that share their mnemonic.
+impl PartialEq for Operation {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Custom(a), Self::Custom(b)) => Rc::ptr_eq(a, b),
+            (Self::Custom(_), _) | (_, Self::Custom(_)) => false,
+            _ => self.mnemonic() == other.mnemonic(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Instruction {
+    pub op: Operation,
+    pub arg: i32,
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {:+}", self.op.mnemonic(), self.arg)
+    }
+}
+
+impl FromStr for Instruction {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        InstructionSet::default().parse(s)
+    }
+}
+
+#[derive(Default, Clone)]
+pub struct InstructionSet {
+    custom: Vec<Rc<dyn Opcode>>,
+}
+
+impl InstructionSet {
+    pub fn with(mut self, op: impl Opcode + 'static) -> Self {
+        self.custom.push(Rc::new(op));
+        self
+    }
+
+    pub fn parse(&self, s: &str) -> Result<Instruction, Error> {
+        let mut words = s.split_whitespace();
+        let mnemonic = words.next().ok_or("missing opcode")?;
+        let arg = words.next().ok_or("missing argument")?.parse()?;
+        if words.next().is_some() {
+            return Err("trailing garbage after instruction".into());
+        }
+
+        let op = match mnemonic {
+            "acc" => Operation::Acc,
+            "jmp" => Operation::Jmp,
+            "nop" => Operation::Nop,
+            _ => self
+                .custom
+                .iter()
+                .find(|op| op.mnemonic() == mnemonic)
+                .map(|op| Operation::Custom(op.clone()))
+                .ok_or("bad opcode")?,
+        };
+        Ok(Instruction { op, arg })
+    }
+
+    pub fn parse_program(&self, data: impl BufRead) -> Result<Vec<Instruction>, Error> {
+        data.lines().map(|line| self.parse(&line?)).collect()
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Exit {
+    Loop(i32),
+    Terminate(i32),
+    Segfault(i32),
+    Breakpoint(i32),
+    Limit(usize),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct TraceEntry {
+    pub ip: i32,
+    pub before: Cpu,
+    pub after: Cpu,
+}
+
+pub struct Vm {
+    program: Vec<Instruction>,
+    cpu: Cpu,
+    visited: Vec<bool>,
+    executed: usize,
+    breakpoints: HashSet<i32>,
+    paused_at: Option<i32>,
+    trace: Option<Vec<TraceEntry>>,
+    limit: Option<usize>,
+    detect_loops: bool,
+}
+
+impl Vm {
+    pub fn new(program: Vec<Instruction>) -> Self {
+        let visited = vec![false; program.len()];
+        Self {
+            program,
+            cpu: Cpu::default(),
+            visited,
+            executed: 0,
+            breakpoints: HashSet::new(),
+            paused_at: None,
+            trace: None,
+            limit: None,
+            detect_loops: true,
+        }
+    }
+
+    pub fn with_trace(mut self) -> Self {
+        self.trace = Some(Vec::new());
+        self
+    }
+
+    pub fn with_limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    pub fn with_loop_detection(mut self, enabled: bool) -> Self {
+        self.detect_loops = enabled;
+        self
+    }
+
+    pub fn program(&self) -> &[Instruction] {
+        &self.program
+    }
+
+    pub fn cpu(&self) -> Cpu {
+        self.cpu
+    }
+
+    pub fn executed(&self) -> usize {
+        self.executed
+    }
+
+    pub fn trace(&self) -> &[TraceEntry] {
+        self.trace.as_deref().unwrap_or(&[])
+    }
+
+    pub fn breakpoints(&self) -> &HashSet<i32> {
+        &self.breakpoints
+    }
+
+    pub fn toggle_breakpoint(&mut self, ip: i32) -> bool {
+        if self.breakpoints.remove(&ip) {
+            false
+        } else {
+            self.breakpoints.insert(ip);
+            true
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.cpu = Cpu::default();
+        self.visited.iter_mut().for_each(|v| *v = false);
+        self.executed = 0;
+        self.paused_at = None;
+        if let Some(trace) = &mut self.trace {
+            trace.clear();
+        }
+    }
+
+    pub fn current(&self) -> Option<&Instruction> {
+        if self.cpu.ip < 0 {
+            return None;
+        }
+        self.program.get(self.cpu.ip as usize)
+    }
+
+    /// Executes a single instruction, or returns why that isn't possible.
+    pub fn step(&mut self) -> Option<Exit> {
+        let ip = self.cpu.ip;
+        if ip == self.program.len() as i32 {
+            return Some(Exit::Terminate(self.cpu.acc));
+        } else if ip < 0 || ip > self.program.len() as i32 {
+            return Some(Exit::Segfault(ip));
+        } else if self.detect_loops && self.visited[ip as usize] {
+            return Some(Exit::Loop(self.cpu.acc));
+        } else if self.limit.is_some_and(|limit| self.executed >= limit) {
+            return Some(Exit::Limit(self.executed));
+        }
+
+        let before = self.cpu;
+        let instr = &self.program[ip as usize];
+        let mut after = self.cpu;
+        // an arithmetic overflow faults at the instruction, leaving the vm untouched
+        match instr
+            .op
+            .execute(instr.arg, &mut after)
+            .and_then(|offset| ip.checked_add(offset))
+        {
+            Some(next) => after.ip = next,
+            None => return Some(Exit::Segfault(ip)),
+        }
+        self.cpu = after;
+        self.visited[ip as usize] = true;
+        self.executed += 1;
+
+        if let Some(trace) = &mut self.trace {
+            trace.push(TraceEntry {
+                ip,
+                before,
+                after: self.cpu,
+            });
+        }
+        None
+    }
+
+    /// Runs until the program stops. Running again after a breakpoint resumes past it.
+    pub fn run(&mut self) -> Exit {
+        loop {
+            let ip = self.cpu.ip;
+            let resuming = self.paused_at.take() == Some(ip);
+            if !resuming && self.breakpoints.contains(&ip) {
+                self.paused_at = Some(ip);
+                return Exit::Breakpoint(ip);
+            }
+            if let Some(exit) = self.step() {
+                return exit;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn program(lines: &[&str]) -> Vec<Instruction> {
+        lines.iter().map(|s| s.parse().unwrap()).collect()
+    }
+
+    struct Mul;
+
+    impl Opcode for Mul {
+        fn mnemonic(&self) -> &'static str {
+            "mul"
+        }
+
+        fn execute(&self, arg: i32, cpu: &mut Cpu) -> Option<i32> {
+            cpu.acc = cpu.acc.checked_mul(arg)?;
+            Some(1)
+        }
+    }
+
+    #[test]
+    fn test_vm_segfault() {
+        assert_eq!(Vm::new(program(&["jmp -1"])).run(), Exit::Segfault(-1));
+        assert_eq!(Vm::new(program(&["jmp +2"])).run(), Exit::Segfault(2));
+    }
+
+    #[test]
+    fn test_vm_overflow() {
+        let mut vm = Vm::new(program(&["nop +0", "jmp +2147483647"]));
+        assert_eq!(vm.run(), Exit::Segfault(1));
+        assert_eq!(vm.cpu(), Cpu { ip: 1, acc: 0 });
+
+        let mut vm = Vm::new(program(&["acc +2147483647", "acc +2147483647"]));
+        assert_eq!(vm.run(), Exit::Segfault(1));
+        assert_eq!(
+            vm.cpu(),
+            Cpu {
+                ip: 1,
+                acc: i32::MAX
+            }
+        );
+        assert_eq!(vm.executed(), 1);
+    }
+
+    #[test]
+    fn test_vm_breakpoints_and_trace() {
+        let mut vm = Vm::new(program(&["acc +1", "acc +2", "acc +3"])).with_trace();
+        vm.toggle_breakpoint(2);
+        assert_eq!(vm.run(), Exit::Breakpoint(2));
+        assert_eq!(vm.cpu().acc, 3);
+        assert_eq!(vm.run(), Exit::Terminate(6));
+        assert_eq!(vm.trace().len(), 3);
+        assert_eq!(vm.trace()[1].after, Cpu { ip: 2, acc: 3 });
+
+        let mut vm = Vm::new(program(&["acc +1", "jmp -1"])).with_loop_detection(false);
+        vm.toggle_breakpoint(0);
+        assert_eq!(vm.run(), Exit::Breakpoint(0));
+        assert_eq!(vm.executed(), 0);
+        assert_eq!(vm.run(), Exit::Breakpoint(0));
+        assert_eq!(vm.cpu().acc, 1);
+    }
+
+    #[test]
+    fn test_vm_limit() {
+        let mut vm = Vm::new(program(&["jmp +0"]))
+            .with_loop_detection(false)
+            .with_limit(100);
+        assert_eq!(vm.run(), Exit::Limit(100));
+    }
+
+    #[test]
+    fn test_vm_custom_opcode() {
+        let set = InstructionSet::default().with(Mul);
+        let prog = ["acc +3", "mul -4", "nop +0"]
+            .iter()
+            .map(|s| set.parse(s).unwrap())
+            .collect();
+        assert_eq!(Vm::new(prog).run(), Exit::Terminate(-12));
+        assert!("mul +2".parse::<Instruction>().is_err());
+        assert!("acc".parse::<Instruction>().is_err());
+
+        let mul = set.parse("mul +2").unwrap();
+        assert_eq!(mul, set.parse("mul +2").unwrap());
+        let other = InstructionSet::default().with(Mul);
+        assert_ne!(mul, other.parse("mul +2").unwrap());
+        assert_ne!(mul.op, Operation::Nop);
+    }
+}
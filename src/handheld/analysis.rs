@@ -0,0 +1,163 @@
+use super::*;
+
+pub fn flipped(op: &Operation) -> Option<Operation> {
+    match op {
+        Operation::Jmp => Some(Operation::Nop),
+        Operation::Nop => Some(Operation::Jmp),
+        _ => None,
+    }
+}
+
+/// Custom opcodes are assumed to have state-independent control flow. An instruction that
+/// faults, or whose target overflows, jumps to -1 so it can never terminate.
+fn target(ip: usize, instr: &Instruction) -> i32 {
+    instr
+        .op
+        .execute(instr.arg, &mut Cpu::default())
+        .and_then(|offset| (ip as i32).checked_add(offset))
+        .unwrap_or(-1)
+}
+
+#[derive(Debug, Clone)]
+pub struct ControlFlow {
+    successors: Vec<i32>,
+    terminating: Vec<bool>,
+}
+
+impl ControlFlow {
+    pub fn new(program: &[Instruction]) -> Self {
+        let len = program.len();
+        let successors = program
+            .iter()
+            .enumerate()
+            .map(|(ip, instr)| target(ip, instr))
+            .collect::<Vec<_>>();
+
+        let mut predecessors = vec![Vec::new(); len + 1];
+        for (ip, &succ) in successors.iter().enumerate() {
+            if (0..=len as i32).contains(&succ) {
+                predecessors[succ as usize].push(ip);
+            }
+        }
+
+        let mut terminating = vec![false; len + 1];
+        terminating[len] = true;
+        let mut stack = vec![len];
+        while let Some(ip) = stack.pop() {
+            for &pred in &predecessors[ip] {
+                if !terminating[pred] {
+                    terminating[pred] = true;
+                    stack.push(pred);
+                }
+            }
+        }
+
+        Self {
+            successors,
+            terminating,
+        }
+    }
+
+    pub fn successor(&self, ip: usize) -> i32 {
+        self.successors[ip]
+    }
+
+    pub fn terminates_from(&self, ip: i32) -> bool {
+        ip >= 0 && self.terminating.get(ip as usize).copied().unwrap_or(false)
+    }
+
+    /// The instructions executed from the start, in order, up to termination or the first repeat.
+    pub fn execution_path(&self) -> Vec<usize> {
+        let mut seen = vec![false; self.successors.len()];
+        let mut path = Vec::new();
+        let mut ip = 0;
+        while ip >= 0 && (ip as usize) < seen.len() && !seen[ip as usize] {
+            seen[ip as usize] = true;
+            path.push(ip as usize);
+            ip = self.successors[ip as usize];
+        }
+        path
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fix {
+    pub ip: usize,
+    pub op: Operation,
+}
+
+impl Fix {
+    pub fn apply(&self, program: &mut [Instruction]) {
+        program[self.ip].op = self.op.clone();
+    }
+}
+
+/// Every single `jmp`/`nop` flip that makes the program terminate.
+pub fn find_fixes(program: &[Instruction]) -> Vec<Fix> {
+    let cfg = ControlFlow::new(program);
+    if cfg.terminates_from(0) {
+        return Vec::new();
+    }
+
+    let mut fixes = Vec::new();
+    for ip in cfg.execution_path() {
+        let instr = &program[ip];
+        if let Some(op) = flipped(&instr.op) {
+            let swapped = Instruction { op, arg: instr.arg };
+            if cfg.terminates_from(target(ip, &swapped)) {
+                fixes.push(Fix { ip, op: swapped.op });
+            }
+        }
+    }
+    fixes
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn program(lines: &[&str]) -> Vec<Instruction> {
+        lines.iter().map(|s| s.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn test_analysis_single_fix() {
+        let prog = program(&[
+            "nop +0", "acc +1", "jmp +4", "acc +3", "jmp -3", "acc -99", "acc +1", "jmp -4",
+            "acc +6",
+        ]);
+        let cfg = ControlFlow::new(&prog);
+        assert!(!cfg.terminates_from(0));
+        assert!(cfg.terminates_from(8));
+        assert_eq!(
+            find_fixes(&prog),
+            vec![Fix {
+                ip: 7,
+                op: Operation::Nop
+            }]
+        );
+    }
+
+    #[test]
+    fn test_analysis_multiple_fixes() {
+        let prog = program(&["nop +2", "jmp -1"]);
+        let fixes = find_fixes(&prog);
+        assert_eq!(fixes.iter().map(|f| f.ip).collect::<Vec<_>>(), vec![0, 1]);
+        assert!(find_fixes(&program(&["acc +1"])).is_empty());
+    }
+
+    #[test]
+    fn test_analysis_overflowing_jump() {
+        let prog = program(&["nop +0", "nop +2147483647", "jmp -2"]);
+        let cfg = ControlFlow::new(&prog);
+        assert_eq!(cfg.successor(1), 2);
+        assert_eq!(
+            find_fixes(&prog).iter().map(|f| f.ip).collect::<Vec<_>>(),
+            vec![2]
+        );
+
+        let cfg = ControlFlow::new(&program(&["nop +0", "jmp +2147483647"]));
+        assert_eq!(cfg.successor(1), -1);
+        assert!(!cfg.terminates_from(0));
+    }
+}
@@ -0,0 +1,370 @@
+use crate::Error;
+
+use itertools::Itertools;
+use std::{
+    f64::consts::PI,
+    fmt::Write,
+    ops::{Add, Mul, Neg, Sub},
+};
+
+/// Axial hex coordinates; the implied third cube coordinate is `-q - r`.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Axial {
+    pub q: i32,
+    pub r: i32,
+}
+
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Hash)]
+pub struct Cube {
+    pub q: i32,
+    pub r: i32,
+    pub s: i32,
+}
+
+impl Cube {
+    pub fn new(q: i32, r: i32, s: i32) -> Result<Self, Error> {
+        if q + r + s != 0 {
+            return Err("cube coordinates must sum to zero".into());
+        }
+        Ok(Self { q, r, s })
+    }
+
+    pub fn distance(self, other: Self) -> u32 {
+        ((self.q - other.q).unsigned_abs()
+            + (self.r - other.r).unsigned_abs()
+            + (self.s - other.s).unsigned_abs())
+            / 2
+    }
+}
+
+impl From<Axial> for Cube {
+    fn from(a: Axial) -> Self {
+        Self {
+            q: a.q,
+            r: a.r,
+            s: -a.q - a.r,
+        }
+    }
+}
+
+impl From<Cube> for Axial {
+    fn from(c: Cube) -> Self {
+        Self { q: c.q, r: c.r }
+    }
+}
+
+impl Axial {
+    pub const ORIGIN: Self = Self { q: 0, r: 0 };
+
+    pub fn new(q: i32, r: i32) -> Self {
+        Self { q, r }
+    }
+
+    pub fn neighbor(self, dir: Direction) -> Self {
+        self + dir.offset()
+    }
+
+    pub fn neighbors(self) -> impl Iterator<Item = Self> {
+        Direction::ALL.iter().map(move |&d| self.neighbor(d))
+    }
+
+    pub fn distance(self, other: Self) -> u32 {
+        Cube::from(self).distance(other.into())
+    }
+
+    /// The hexes exactly `radius` steps away, walking around the ring from its south-west corner
+    /// on a pointy-top layout.
+    pub fn ring(self, radius: u32) -> impl Iterator<Item = Self> {
+        let start = self + Direction::ALL[4].offset() * radius as i32;
+        let len = if radius == 0 { 1 } else { 6 * radius };
+        (0..len).scan(start, move |hex, i| {
+            let current = *hex;
+            if let Some(side) = i.checked_div(radius) {
+                *hex = hex.neighbor(Direction::ALL[side as usize]);
+            }
+            Some(current)
+        })
+    }
+
+    /// Every hex within `radius` steps, ring by ring outwards from the centre.
+    pub fn spiral(self, radius: u32) -> impl Iterator<Item = Self> {
+        (0..=radius).flat_map(move |r| self.ring(r))
+    }
+}
+
+impl Add for Axial {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self::new(self.q + other.q, self.r + other.r)
+    }
+}
+
+impl Sub for Axial {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        Self::new(self.q - other.q, self.r - other.r)
+    }
+}
+
+impl Neg for Axial {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        Self::new(-self.q, -self.r)
+    }
+}
+
+impl Mul<i32> for Axial {
+    type Output = Self;
+
+    fn mul(self, k: i32) -> Self {
+        Self::new(self.q * k, self.r * k)
+    }
+}
+
+/// One of the six directions out of a hex, numbered counter-clockwise from `+q`. What each is
+/// called depends on the `Layout`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct Direction(u8);
+
+impl Direction {
+    pub const ALL: [Self; 6] = [Self(0), Self(1), Self(2), Self(3), Self(4), Self(5)];
+
+    pub fn offset(self) -> Axial {
+        let (q, r) = [(1, 0), (1, -1), (0, -1), (-1, 0), (-1, 1), (0, 1)][self.0 as usize];
+        Axial::new(q, r)
+    }
+
+    /// Turns `steps` sixths of a full turn counter-clockwise.
+    pub fn rotate(self, steps: i32) -> Self {
+        Self((self.0 as i32 + steps).rem_euclid(6) as u8)
+    }
+
+    pub fn opposite(self) -> Self {
+        self.rotate(3)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Layout {
+    /// Rows of hexes with pointed tops; directions are e, ne, nw, w, sw and se.
+    PointyTop,
+    /// Columns of hexes with flat tops; directions are se, ne, n, nw, sw and s.
+    FlatTop,
+}
+
+impl Layout {
+    fn names(self) -> [&'static str; 6] {
+        match self {
+            Self::PointyTop => ["e", "ne", "nw", "w", "sw", "se"],
+            Self::FlatTop => ["se", "ne", "n", "nw", "sw", "s"],
+        }
+    }
+
+    pub fn name(self, dir: Direction) -> &'static str {
+        self.names()[dir.0 as usize]
+    }
+
+    pub fn parse_direction(self, s: &str) -> Result<Direction, Error> {
+        self.names()
+            .iter()
+            .position(|&n| n == s)
+            .map(|i| Direction::ALL[i])
+            .ok_or(Error::Syntax {
+                message: "unknown direction",
+                span: 0..s.len(),
+            })
+    }
+
+    /// Splits a run of direction names with no separators, such as `esenee`.
+    pub fn parse_path(self, s: &str) -> Result<Vec<Direction>, Error> {
+        let names = self.names();
+        let mut path = Vec::new();
+        let mut i = 0;
+        while i < s.len() {
+            let (dir, len) = [2, 1]
+                .iter()
+                .filter_map(|&len| {
+                    let name = s.get(i..i + len)?;
+                    let dir = names.iter().position(|&n| n == name)?;
+                    Some((Direction::ALL[dir], len))
+                })
+                .next()
+                .ok_or_else(|| Error::Syntax {
+                    message: "unknown direction",
+                    span: i..i + s[i..].chars().next().map_or(1, char::len_utf8),
+                })?;
+            path.push(dir);
+            i += len;
+        }
+        Ok(path)
+    }
+
+    /// The centre of `hex` when hexes have circumradius `size`, with y pointing down.
+    pub fn to_pixel(self, hex: Axial, size: f64) -> (f64, f64) {
+        let (q, r) = (f64::from(hex.q), f64::from(hex.r));
+        let sqrt3 = 3f64.sqrt();
+        match self {
+            Self::PointyTop => (size * (sqrt3 * q + sqrt3 / 2.0 * r), size * 1.5 * r),
+            Self::FlatTop => (size * 1.5 * q, size * (sqrt3 / 2.0 * q + sqrt3 * r)),
+        }
+    }
+
+    pub fn corners(self, hex: Axial, size: f64) -> [(f64, f64); 6] {
+        let (x, y) = self.to_pixel(hex, size);
+        let offset = match self {
+            Self::PointyTop => -30.0,
+            Self::FlatTop => 0.0,
+        };
+        let mut corners = [(0.0, 0.0); 6];
+        for (i, corner) in corners.iter_mut().enumerate() {
+            let angle = (60.0 * i as f64 + offset) * PI / 180.0;
+            *corner = (x + size * angle.cos(), y + size * angle.sin());
+        }
+        corners
+    }
+
+    /// Draws each hex as a polygon filled with the given colour.
+    pub fn to_svg<'a>(
+        self,
+        hexes: impl IntoIterator<Item = (Axial, &'a str)>,
+        size: f64,
+    ) -> String {
+        let hexes = hexes.into_iter().collect_vec();
+        let points = || {
+            hexes
+                .iter()
+                .flat_map(|&(hex, _)| self.corners(hex, size).to_vec())
+        };
+        let (min_x, max_x) = points()
+            .map(|p| p.0)
+            .minmax_by(|a, b| a.total_cmp(b))
+            .into_option()
+            .unwrap_or((0.0, 0.0));
+        let (min_y, max_y) = points()
+            .map(|p| p.1)
+            .minmax_by(|a, b| a.total_cmp(b))
+            .into_option()
+            .unwrap_or((0.0, 0.0));
+        let margin = size / 2.0;
+
+        let mut out = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"{:.2} {:.2} {:.2} {:.2}\">\n",
+            min_x - margin,
+            min_y - margin,
+            max_x - min_x + 2.0 * margin,
+            max_y - min_y + 2.0 * margin
+        );
+        for (hex, fill) in &hexes {
+            let points = self
+                .corners(*hex, size)
+                .iter()
+                .map(|(x, y)| format!("{:.2},{:.2}", x, y))
+                .join(" ");
+            writeln!(
+                out,
+                "  <polygon points=\"{}\" fill=\"{}\" stroke=\"gray\" stroke-width=\"{:.2}\"/>",
+                points,
+                fill,
+                size / 20.0
+            )
+            .unwrap();
+        }
+        out += "</svg>\n";
+        out
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_coordinates() {
+        let a = Axial::new(2, -3);
+        let c = Cube::from(a);
+        assert_eq!(c, Cube::new(2, -3, 1).unwrap());
+        assert_eq!(Axial::from(c), a);
+        assert!(Cube::new(1, 1, 1).is_err());
+
+        assert_eq!(a.distance(Axial::ORIGIN), 3);
+        assert_eq!(a.distance(a), 0);
+        assert_eq!(Axial::new(-1, 4).distance(Axial::new(3, -2)), 6);
+        for dir in Direction::ALL.iter() {
+            assert_eq!(dir.offset().distance(Axial::ORIGIN), 1);
+            assert_eq!(dir.offset(), -dir.opposite().offset());
+        }
+    }
+
+    #[test]
+    fn test_rings() {
+        let center = Axial::new(1, 2);
+        assert_eq!(center.ring(0).collect_vec(), [center]);
+        for radius in 1..5 {
+            let ring = center.ring(radius).collect_vec();
+            assert_eq!(ring.len(), 6 * radius as usize);
+            assert!(ring.iter().all(|h| h.distance(center) == radius));
+            assert_eq!(ring.iter().unique().count(), ring.len());
+            assert!(ring
+                .iter()
+                .zip(ring.iter().cycle().skip(1))
+                .all(|(a, b)| a.distance(*b) == 1));
+        }
+
+        let spiral = center.spiral(3).collect_vec();
+        assert_eq!(spiral.len(), 37);
+        assert_eq!(spiral[0], center);
+        assert_eq!(spiral.iter().unique().count(), 37);
+    }
+
+    #[test]
+    fn test_parse_path() {
+        let pointy = Layout::PointyTop;
+        let path = pointy.parse_path("esenee").unwrap();
+        assert_eq!(path.iter().map(|&d| pointy.name(d)).join(","), "e,se,ne,e");
+        let end = path.iter().fold(Axial::ORIGIN, |h, &d| h.neighbor(d));
+        assert_eq!(end, Axial::new(3, 0));
+
+        let flat = Layout::FlatTop;
+        let path = flat.parse_path("nsnwse").unwrap();
+        assert_eq!(path.iter().map(|&d| flat.name(d)).join(","), "n,s,nw,se");
+        assert_eq!(
+            flat.parse_direction("s").unwrap().opposite(),
+            flat.parse_direction("n").unwrap()
+        );
+
+        let span = |e: Error| match e {
+            Error::Syntax { span, .. } => span,
+            other => panic!("expected syntax error, got {:?}", other),
+        };
+        assert_eq!(span(pointy.parse_path("eenx").unwrap_err()), 2..3);
+        assert_eq!(span(flat.parse_path("nnew").unwrap_err()), 3..4);
+        assert_eq!(span(pointy.parse_direction("n").unwrap_err()), 0..1);
+    }
+
+    #[test]
+    fn test_pixels_and_svg() {
+        for &layout in &[Layout::PointyTop, Layout::FlatTop] {
+            // neighbouring hexes share exactly two corners
+            for dir in Direction::ALL.iter() {
+                let a = layout.corners(Axial::ORIGIN, 10.0);
+                let b = layout.corners(dir.offset(), 10.0);
+                let shared = a
+                    .iter()
+                    .filter(|p| b.iter().any(|q| (p.0 - q.0).hypot(p.1 - q.1) < 1e-9))
+                    .count();
+                assert_eq!(shared, 2);
+            }
+        }
+
+        let svg = Layout::PointyTop.to_svg(
+            vec![(Axial::ORIGIN, "black"), (Axial::new(1, 0), "white")],
+            10.0,
+        );
+        assert!(svg.starts_with("<svg"));
+        assert_eq!(svg.matches("<polygon").count(), 2);
+        assert!(svg.contains("fill=\"black\""));
+    }
+}
@@ -1,10 +1,12 @@
-use std::{fmt::Debug, fs::File, io::BufReader};
+use std::{fs::File, io::BufReader};
 use thiserror::Error;
 
+pub use answer::Answer;
+
 pub trait Challenge {
     type Input;
-    type Output1: Debug;
-    type Output2: Debug;
+    type Output1: Answer;
+    type Output2: Answer;
 
     fn read(data: BufReader<File>) -> Result<Self::Input, Error>;
 
@@ -18,8 +20,22 @@ pub enum Error {
     Io(#[from] std::io::Error),
     #[error("integer parse error")]
     ParseInt(#[from] std::num::ParseIntError),
+    #[error("invalid regex: {0}")]
+    Regex(#[from] regex::Error),
     #[error("Regex \"{0}\" didn't match \"{1}\"")]
     RegexFail(regex::Regex, String),
+    #[error("{message} at {span:?}")]
+    Syntax {
+        message: &'static str,
+        span: std::ops::Range<usize>,
+    },
+    #[error("{message} evaluating {span:?}")]
+    Eval {
+        message: &'static str,
+        span: std::ops::Range<usize>,
+    },
+    #[error("cycle detected: {0}")]
+    Cycle(String),
     #[error("{0}")]
     Generic(&'static str),
 }
@@ -30,6 +46,11 @@ impl From<&'static str> for Error {
     }
 }
 
+pub mod answer;
+pub mod assignment;
 pub mod days;
 pub mod extensions;
+pub mod handheld;
+pub mod hex;
+pub mod number_theory;
 pub mod prelude;
@@ -1,7 +1,15 @@
-use std::{fs::File, io::BufReader};
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, Write},
+};
 
-use aoc_2020::{days::*, Challenge};
+use aoc_2020::{
+    days::*,
+    handheld::{Exit, InstructionSet, Vm},
+    Answer, Challenge,
+};
 
+#[derive(Copy, Clone)]
 enum Part {
     Part1,
     Part2,
@@ -10,26 +18,57 @@ enum Part {
 fn main() {
     let mut args = std::env::args();
     let _ = args.next().unwrap();
-    let day = args.next().unwrap().parse::<u8>().unwrap();
-    let part = match args.next().unwrap().as_str() {
+    let first = args.next().unwrap();
+    if first == "vm" {
+        let path = args.next().unwrap_or_else(|| "input/day08.txt".into());
+        return vm_repl(&path);
+    } else if first == "regex" {
+        let path = args.next().unwrap_or_else(|| "input/day19.txt".into());
+        let data = BufReader::new(File::open(path).unwrap());
+        let (rules, _) = day19::Day19::read(data).unwrap();
+        match day19::compile_regex(&rules, 0) {
+            Ok(re) => println!("{}", re.as_str()),
+            Err(e) => eprintln!("{}", e),
+        }
+        return;
+    }
+    if first == "verify" {
+        let path = args.next().unwrap_or_else(|| "input/answers.txt".into());
+        return verify(&path);
+    }
+
+    let day = first.parse::<u8>().unwrap();
+    let part = parse_part(&args.next().unwrap());
+
+    let answer = solve(day, part);
+    println!("{}", answer.canonical());
+    if let Some(rendered) = answer.render() {
+        println!("{}", rendered);
+    }
+}
+
+fn parse_part(s: &str) -> Part {
+    match s {
         "1" => Part::Part1,
         "2" => Part::Part2,
-        _ => panic!(),
-    };
+        _ => panic!("no such part"),
+    }
+}
 
+fn solve(day: u8, part: Part) -> Box<dyn Answer> {
     let data = BufReader::new(File::open(format!("input/day{:02}.txt", day)).unwrap());
 
     macro_rules! day {
         ($day:ty) => {{
             let input = <$day>::read(data).unwrap();
             match part {
-                Part::Part1 => format!("{:?}", <$day>::part1(input)),
-                Part::Part2 => format!("{:?}", <$day>::part2(input)),
+                Part::Part1 => Box::new(<$day>::part1(input)) as Box<dyn Answer>,
+                Part::Part2 => Box::new(<$day>::part2(input)),
             }
         }};
     }
 
-    let output = match day {
+    match day {
         1 => day!(day01::Day01),
         2 => day!(day02::Day02),
         3 => day!(day03::Day03),
@@ -56,6 +95,119 @@ fn main() {
         24 => day!(day24::Day24),
         25 => day!(day25::Day25),
         _ => panic!("no such day"),
-    };
-    println!("{}", output);
+    }
+}
+
+/// Checks answers against a file of `<day> <part> <answer>` lines.
+fn verify(path: &str) {
+    let data = BufReader::new(File::open(path).unwrap());
+    let mut failures = 0;
+    for line in data.lines() {
+        let line = line.unwrap();
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let mut fields = line.splitn(3, ' ');
+        let (day, part) = (fields.next().unwrap(), fields.next().unwrap());
+        let expected = fields.next().unwrap_or("");
+        let answer = solve(day.parse().unwrap(), parse_part(part));
+        if answer.check(expected) {
+            println!("day {} part {}: ok", day, part);
+        } else {
+            println!(
+                "day {} part {}: got {}, expected {}",
+                day,
+                part,
+                answer.canonical(),
+                expected
+            );
+            failures += 1;
+        }
+    }
+
+    if failures > 0 {
+        println!("{} answers wrong", failures);
+        std::process::exit(1);
+    }
+}
+
+fn vm_repl(path: &str) {
+    let data = BufReader::new(File::open(path).unwrap());
+    let program = InstructionSet::default().parse_program(data).unwrap();
+    let mut vm = Vm::new(program).with_trace();
+
+    let stdin = std::io::stdin();
+    let mut lines = stdin.lock().lines();
+    loop {
+        let cpu = vm.cpu();
+        match vm.current() {
+            Some(instr) => print!("[ip={} acc={}] {} > ", cpu.ip, cpu.acc, instr),
+            None => print!("[ip={} acc={}] > ", cpu.ip, cpu.acc),
+        }
+        std::io::stdout().flush().unwrap();
+
+        let line = match lines.next() {
+            Some(line) => line.unwrap(),
+            None => break,
+        };
+        let mut words = line.split_whitespace();
+        let arg = |w: Option<&str>| w.and_then(|w| w.parse::<i32>().ok());
+
+        match words.next() {
+            Some("s") | Some("step") => {
+                for _ in 0..arg(words.next()).unwrap_or(1) {
+                    if let Some(exit) = vm.step() {
+                        report(exit);
+                        break;
+                    }
+                }
+            }
+            Some("c") | Some("continue") => report(vm.run()),
+            Some("b") | Some("break") => match arg(words.next()) {
+                Some(ip) if vm.toggle_breakpoint(ip) => println!("breakpoint set at {}", ip),
+                Some(ip) => println!("breakpoint cleared at {}", ip),
+                None => println!("breakpoints: {:?}", vm.breakpoints()),
+            },
+            Some("l") | Some("list") => {
+                let center = arg(words.next()).unwrap_or(cpu.ip);
+                for ip in center.saturating_sub(5).max(0)..center.saturating_add(6) {
+                    if let Some(instr) = vm.program().get(ip as usize) {
+                        let marker = if ip == cpu.ip { '>' } else { ' ' };
+                        println!("{} {:4} {}", marker, ip, instr);
+                    }
+                }
+            }
+            Some("t") | Some("trace") => {
+                let n = arg(words.next()).unwrap_or(10) as usize;
+                let trace = vm.trace();
+                for entry in &trace[trace.len().saturating_sub(n)..] {
+                    println!(
+                        "{:4} {:<10} acc {} -> {}",
+                        entry.ip,
+                        vm.program()[entry.ip as usize].to_string(),
+                        entry.before.acc,
+                        entry.after.acc
+                    );
+                }
+            }
+            Some("reset") => vm.reset(),
+            Some("q") | Some("quit") => break,
+            Some(_) => println!(
+                "commands: step [n], continue, break [ip], list [ip], trace [n], reset, quit"
+            ),
+            None => {}
+        }
+    }
+}
+
+fn report(exit: Exit) {
+    match exit {
+        Exit::Loop(acc) => println!("infinite loop detected (acc={})", acc),
+        Exit::Terminate(acc) => println!("program terminated (acc={})", acc),
+        Exit::Segfault(ip) => println!("segfault at ip={}", ip),
+        Exit::Breakpoint(ip) => println!("breakpoint hit at ip={}", ip),
+        Exit::Limit(n) => println!("instruction limit reached after {} steps", n),
+    }
 }
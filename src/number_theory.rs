@@ -0,0 +1,271 @@
+use crate::Error;
+
+use std::{collections::HashMap, convert::TryFrom};
+
+pub fn gcd(mut a: u128, mut b: u128) -> u128 {
+    while b != 0 {
+        let r = a % b;
+        a = b;
+        b = r;
+    }
+    a
+}
+
+/// Returns `(g, x, y)` such that `a * x + b * y == g == gcd(a, b)`.
+pub fn extended_gcd(a: i128, b: i128) -> (i128, i128, i128) {
+    let (mut old_r, mut r) = (a, b);
+    let (mut old_s, mut s) = (1, 0);
+    let (mut old_t, mut t) = (0, 1);
+    while r != 0 {
+        let q = old_r / r;
+        (old_r, r) = (r, old_r - q * r);
+        (old_s, s) = (s, old_s - q * s);
+        (old_t, t) = (t, old_t - q * t);
+    }
+    if old_r < 0 {
+        (-old_r, -old_s, -old_t)
+    } else {
+        (old_r, old_s, old_t)
+    }
+}
+
+pub fn mod_inverse(a: u128, m: u128) -> Option<u128> {
+    if m == 0 {
+        return None;
+    }
+    let (a, m) = (i128::try_from(a).ok()?, i128::try_from(m).ok()?);
+    match extended_gcd(a.rem_euclid(m), m) {
+        (1, x, _) => Some(x.rem_euclid(m) as u128),
+        _ => None,
+    }
+}
+
+/// `a * b (mod m)`, or `None` if `m` is zero.
+pub fn mul_mod(a: u128, b: u128, m: u128) -> Option<u128> {
+    (m != 0).then(|| mul_mod_nonzero(a, b, m))
+}
+
+fn mul_mod_nonzero(a: u128, b: u128, m: u128) -> u128 {
+    let (mut a, mut b) = (a % m, b % m);
+    if let Some(product) = a.checked_mul(b) {
+        return product % m;
+    }
+
+    let mut result = 0;
+    while b > 0 {
+        if b & 1 == 1 {
+            result = add_mod(result, a, m);
+        }
+        a = add_mod(a, a, m);
+        b >>= 1;
+    }
+    result
+}
+
+fn add_mod(a: u128, b: u128, m: u128) -> u128 {
+    if a >= m - b {
+        a - (m - b)
+    } else {
+        a + b
+    }
+}
+
+/// `base^exp (mod m)`, or `None` if `m` is zero.
+pub fn pow_mod(base: u128, exp: u128, m: u128) -> Option<u128> {
+    (m != 0).then(|| pow_mod_nonzero(base, exp, m))
+}
+
+fn pow_mod_nonzero(base: u128, mut exp: u128, m: u128) -> u128 {
+    let mut base = base % m;
+    let mut result = 1 % m;
+    while exp > 0 {
+        if exp & 1 == 1 {
+            result = mul_mod_nonzero(result, base, m);
+        }
+        base = mul_mod_nonzero(base, base, m);
+        exp >>= 1;
+    }
+    result
+}
+
+/// The largest baby-step table `discrete_log` will build, enough for moduli up to 2^40.
+const MAX_BABY_STEPS: u128 = 1 << 20;
+
+/// The smallest `x` with `base^x == target (mod m)`, by baby-step giant-step. `base` must be
+/// invertible modulo `m`, and `m` at most `2^40`.
+pub fn discrete_log(base: u128, target: u128, m: u128) -> Result<u128, Error> {
+    if m == 0 {
+        return Err("modulus must be positive".into());
+    }
+    let (base, target) = (base % m, target % m);
+    let base_inv = mod_inverse(base, m).ok_or("base is not invertible")?;
+
+    let mut n = (m as f64).sqrt() as u128;
+    while n.checked_mul(n).ok_or("modulus too large")? < m {
+        n += 1;
+    }
+    if n > MAX_BABY_STEPS {
+        return Err("modulus too large".into());
+    }
+    let table_size = usize::try_from(n).map_err(|_| "modulus too large")?;
+
+    // baby steps: base^j for j < n, keeping the smallest j for each value
+    let mut baby = HashMap::with_capacity(table_size);
+    let mut power = 1 % m;
+    for j in 0..n {
+        baby.entry(power).or_insert(j);
+        power = mul_mod_nonzero(power, base, m);
+    }
+
+    // giant steps: target * base^(-n*i) for i < n
+    let factor = pow_mod_nonzero(base_inv, n, m);
+    let mut gamma = target;
+    for i in 0..n {
+        if let Some(&j) = baby.get(&gamma) {
+            return Ok(i * n + j);
+        }
+        gamma = mul_mod_nonzero(gamma, factor, m);
+    }
+    Err("no discrete logarithm exists".into())
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Congruence {
+    pub residue: u128,
+    pub modulus: u128,
+}
+
+impl Congruence {
+    pub fn new(residue: u128, modulus: u128) -> Result<Self, Error> {
+        if modulus == 0 {
+            return Err("modulus must be positive".into());
+        }
+        Ok(Self {
+            residue: residue % modulus,
+            modulus,
+        })
+    }
+
+    /// The smallest value at or after `t` that satisfies the congruence.
+    pub fn next_from(self, t: u128) -> Result<u128, Error> {
+        let Self { residue, modulus } = Self::new(self.residue, self.modulus)?;
+        let offset = t % modulus;
+        let wait = if residue >= offset {
+            residue - offset
+        } else {
+            modulus - (offset - residue)
+        };
+        Ok(t.checked_add(wait).ok_or("value overflowed")?)
+    }
+
+    /// Combines two congruences into one, whether or not their moduli are coprime.
+    pub fn merge(self, other: Self) -> Result<Self, Error> {
+        let (this, other) = (
+            Self::new(self.residue, self.modulus)?,
+            Self::new(other.residue, other.modulus)?,
+        );
+        let g = gcd(this.modulus, other.modulus);
+        let from = this.residue % other.modulus;
+        let diff = if other.residue >= from {
+            other.residue - from
+        } else {
+            other.modulus - (from - other.residue)
+        };
+        if diff % g != 0 {
+            return Err("inconsistent congruences".into());
+        }
+
+        let lcm = (this.modulus / g)
+            .checked_mul(other.modulus)
+            .ok_or("combined modulus overflowed")?;
+        let m = other.modulus / g;
+        let inv = mod_inverse(this.modulus / g, m).ok_or("modulus too large")?;
+        let k = mul_mod_nonzero(diff / g, inv, m);
+
+        let residue = this
+            .modulus
+            .checked_mul(k)
+            .and_then(|step| step.checked_add(this.residue))
+            .ok_or("combined residue overflowed")?;
+        Self::new(residue, lcm)
+    }
+}
+
+pub fn crt(congruences: impl IntoIterator<Item = Congruence>) -> Result<Congruence, Error> {
+    congruences
+        .into_iter()
+        .try_fold(Congruence::new(0, 1)?, Congruence::merge)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_extended_gcd() {
+        assert_eq!(extended_gcd(240, 46), (2, -9, 47));
+        assert_eq!(mod_inverse(3, 11), Some(4));
+        assert_eq!(mod_inverse(6, 9), None);
+        assert_eq!(mul_mod(u128::MAX - 1, u128::MAX - 1, u128::MAX), Some(1));
+        assert_eq!(mod_inverse(3, 0), None);
+        assert_eq!(mul_mod(3, 4, 0), None);
+    }
+
+    #[test]
+    fn test_pow_and_log() {
+        assert_eq!(pow_mod(7, 8, 20201227), Some(5764801));
+        assert_eq!(pow_mod(3, 0, 1), Some(0));
+        assert_eq!(pow_mod(2, 127, u128::MAX), Some(1 << 127));
+        assert_eq!(pow_mod(2, 3, 0), None);
+
+        assert_eq!(discrete_log(7, 5764801, 20201227).unwrap(), 8);
+        assert_eq!(discrete_log(7, 17807724, 20201227).unwrap(), 11);
+        assert_eq!(discrete_log(7, 1, 20201227).unwrap(), 0);
+        for x in [0, 1, 5, 99, 12345].iter() {
+            assert_eq!(
+                discrete_log(5, pow_mod(5, *x, 1_000_003).unwrap(), 1_000_003).unwrap(),
+                *x
+            );
+        }
+
+        // powers of 4 mod 7 cycle through 1, 4, 2
+        assert_eq!(discrete_log(4, 2 + 7, 7).unwrap(), 2);
+        assert!(discrete_log(4, 3, 7).is_err());
+        assert!(discrete_log(6, 2, 9).is_err());
+        assert!(discrete_log(2, 1, 0).is_err());
+        assert!(discrete_log(2, 1, u128::MAX).is_err());
+        assert!(discrete_log(3, 1, 1 << 100).is_err());
+        assert_eq!(discrete_log(3, 3, 1 << 40).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_crt() {
+        let congruence = |r, m| Congruence::new(r, m).unwrap();
+
+        let c = crt(vec![congruence(2, 3), congruence(3, 5), congruence(2, 7)]);
+        assert_eq!(c.unwrap(), congruence(23, 105));
+
+        let c = crt(vec![congruence(2, 6), congruence(8, 10)]);
+        assert_eq!(c.unwrap(), congruence(8, 30));
+
+        assert!(crt(vec![congruence(1, 6), congruence(2, 4)]).is_err());
+        assert!(Congruence::new(1, 0).is_err());
+
+        // moduli near the top of the range report overflow instead of wrapping
+        let c = congruence(1, 2).merge(congruence(u128::MAX - 1, u128::MAX));
+        assert!(c.is_err());
+        let big = (1 << 126) + 1;
+        let c = congruence(0, 2).merge(congruence(big - 1, big));
+        assert_eq!(c.unwrap(), congruence(big - 1, 2 * big));
+
+        assert_eq!(congruence(0, 7).next_from(939).unwrap(), 945);
+        assert_eq!(congruence(3, 7).next_from(3).unwrap(), 3);
+        assert!(congruence(0, 7).next_from(u128::MAX).is_err());
+        let zero = Congruence {
+            residue: 0,
+            modulus: 0,
+        };
+        assert!(zero.next_from(5).is_err());
+        assert!(zero.merge(congruence(1, 2)).is_err());
+    }
+}
@@ -4,6 +4,7 @@ pub use std::{
 };
 
 pub use crate::extensions::*;
+pub use crate::Answer;
 pub use crate::Challenge;
 pub use crate::Error;
 